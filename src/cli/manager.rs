@@ -34,12 +34,39 @@ pub fn is_reset() -> bool {
     return MANAGER.as_ref().clap_matches.is_present("reset");
 }
 
+// Check if software encoding should be used even if hardware encoders are available
+pub fn is_software_encoder() -> bool {
+    return MANAGER.as_ref().clap_matches.is_present("software-encoder");
+}
+
 #[allow(dead_code)]
 // Return the mavlink connection string
 pub fn mavlink_connection_string() -> &'static str {
     return MANAGER.as_ref().clap_matches.value_of("mavlink").unwrap();
 }
 
+// Return the MAVLink system id used by the cameras
+pub fn mavlink_system_id() -> u8 {
+    return MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("mavlink-system-id")
+        .unwrap()
+        .parse()
+        .unwrap();
+}
+
+// Return the MAVLink component id of the first camera, the next cameras use the following ids
+pub fn mavlink_component_id() -> u8 {
+    return MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("mavlink-component-id")
+        .unwrap()
+        .parse()
+        .unwrap();
+}
+
 // Return the desired address for the REST API
 pub fn server_address() -> &'static str {
     return MANAGER
@@ -49,6 +76,63 @@ pub fn server_address() -> &'static str {
         .unwrap();
 }
 
+// Return the default number of threads used by software encoders
+pub fn encoder_threads() -> u32 {
+    return MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("encoder-threads")
+        .unwrap()
+        .parse()
+        .unwrap();
+}
+
+// Return the time that new streams have to start playing before being dropped
+pub fn stream_startup_timeout() -> std::time::Duration {
+    let seconds = MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("stream-startup-timeout")
+        .unwrap()
+        .parse()
+        .unwrap();
+    return std::time::Duration::from_secs(seconds);
+}
+
+// Return the longest delay between the restarts of a failing pipeline
+pub fn max_restart_delay() -> std::time::Duration {
+    let seconds = MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("max-restart-delay")
+        .unwrap()
+        .parse()
+        .unwrap();
+    return std::time::Duration::from_secs(seconds);
+}
+
+// Return the number of failures in a row after which a pipeline is not restarted anymore
+pub fn max_pipeline_failures() -> u32 {
+    return MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("max-pipeline-failures")
+        .unwrap()
+        .parse()
+        .unwrap();
+}
+
+// Return the number of runs in a row that a stream may fail to play before it's held back
+pub fn max_unhealthy_runs() -> u32 {
+    return MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("max-unhealthy-runs")
+        .unwrap()
+        .parse()
+        .unwrap();
+}
+
 pub fn default_settings() -> Option<&'static str> {
     return MANAGER.as_ref().clap_matches.value_of("default-settings");
 }
@@ -81,7 +165,35 @@ fn get_clap_matches<'a>() -> clap::ArgMatches<'a> {
                 .value_name("TYPE:<IP/SERIAL>:<PORT/BAUDRATE>")
                 .help("Sets the mavlink connection string")
                 .takes_value(true)
-                .default_value("udpout:0.0.0.0:14550"),
+                .default_value("udpout:0.0.0.0:14550")
+                .validator(|value| match crate::mavlink::manager::connection_string(&value) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error.to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("mavlink-system-id")
+                .long("mavlink-system-id")
+                .value_name("ID")
+                .help("Sets the MAVLink system id of the cameras")
+                .takes_value(true)
+                .default_value("1")
+                .validator(|value| match value.parse::<u8>() {
+                    Ok(id) if id > 0 => Ok(()),
+                    _ => Err("Should be a number between 1 and 255.".to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("mavlink-component-id")
+                .long("mavlink-component-id")
+                .value_name("ID")
+                .help("Sets the MAVLink component id of the first camera, the next cameras use the following ids")
+                .takes_value(true)
+                .default_value("100")
+                .validator(|value| match value.parse::<u8>() {
+                    Ok(id) if id > 0 => Ok(()),
+                    _ => Err("Should be a number between 1 and 255.".to_string()),
+                }),
         )
         .arg(
             clap::Arg::with_name("default-settings")
@@ -91,6 +203,72 @@ fn get_clap_matches<'a>() -> clap::ArgMatches<'a> {
                 .help("Default settings to be used for different vehicles or environments.")
                 .takes_value(true)
         )
+        .arg(
+            clap::Arg::with_name("encoder-threads")
+                .long("encoder-threads")
+                .value_name("THREADS")
+                .help("Default number of threads used by software encoders, can be changed per stream.")
+                .takes_value(true)
+                .default_value("2")
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(threads) if threads > 0 => Ok(()),
+                    _ => Err("Should be a number bigger than zero.".to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("stream-startup-timeout")
+                .long("stream-startup-timeout")
+                .value_name("SECONDS")
+                .help("Time that streams created by the REST API have to start playing, they are not added if it fails.")
+                .takes_value(true)
+                .default_value("10")
+                .validator(|value| match value.parse::<u64>() {
+                    Ok(seconds) if seconds > 0 => Ok(()),
+                    _ => Err("Should be a number bigger than zero.".to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("max-restart-delay")
+                .long("max-restart-delay")
+                .value_name("SECONDS")
+                .help("Longest delay between the restarts of a failing pipeline, the delay doubles after each failure up to it.")
+                .takes_value(true)
+                .default_value("10")
+                .validator(|value| match value.parse::<u64>() {
+                    Ok(seconds) if seconds > 0 => Ok(()),
+                    _ => Err("Should be a number bigger than zero.".to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("max-pipeline-failures")
+                .long("max-pipeline-failures")
+                .value_name("FAILURES")
+                .help("Number of times in a row that a pipeline may fail before its stream is stopped, it's started again when the stream is enabled.")
+                .takes_value(true)
+                .default_value("10")
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(failures) if failures > 0 => Ok(()),
+                    _ => Err("Should be a number bigger than zero.".to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("max-unhealthy-runs")
+                .long("max-unhealthy-runs")
+                .value_name("RUNS")
+                .help("Number of service runs in a row that a stream may fail to play, it's not started with the service after that until it's enabled again.")
+                .takes_value(true)
+                .default_value("1")
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(runs) if runs > 0 => Ok(()),
+                    _ => Err("Should be a number bigger than zero.".to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("software-encoder")
+                .long("software-encoder")
+                .help("Force software encoding (x264enc), even if hardware encoders are available.")
+                .takes_value(false),
+        )
         .arg(
             clap::Arg::with_name("reset")
                 .long("reset")
@@ -123,5 +301,13 @@ mod tests {
     fn default_arguments() {
         assert_eq!(is_verbose(), false);
         assert_eq!(mavlink_connection_string(), "udpout:0.0.0.0:14550");
+        assert_eq!(mavlink_system_id(), 1);
+        assert_eq!(
+            mavlink_component_id(),
+            mavlink::common::MavComponent::MAV_COMP_ID_CAMERA as u8
+        );
+        assert_eq!(encoder_threads(), 2);
+        assert_eq!(stream_startup_timeout(), std::time::Duration::from_secs(10));
+        assert_eq!(is_software_encoder(), false);
     }
 }
@@ -23,6 +23,10 @@ pub fn udp() -> Vec<VideoAndStreamInformation> {
             let size = format.sizes.first().unwrap();
             VideoAndStreamInformation {
                 name: format!("UDP Stream {}", index),
+                enabled: true,
+                priority: 0,
+                description: None,
+                controls: vec![],
                 stream_information: StreamInformation {
                     endpoints: vec![
                         Url::parse(&format!("udp://192.168.2.1:{}", 5600 + index)).unwrap()
@@ -32,7 +36,24 @@ pub fn udp() -> Vec<VideoAndStreamInformation> {
                         height: size.height,
                         width: size.width,
                         frame_interval: size.intervals.first().unwrap().clone(),
+                        keyframe_interval: None,
+                        encoder_threads: None,
+                        b_frames: None,
+                        pixel_format: PixelFormat::I420,
+                        allow_closest_resolution: false,
+                        io_mode: None,
                     },
+                    udp_buffer_size: None,
+                    multicast_ttl: None,
+                    config_interval: None,
+                    segment_duration: None,
+                    ssrc: None,
+                    seqnum_offset: None,
+                    timestamp_offset: None,
+                    rtp_mtu: None,
+                    overlay: None,
+                    crop: None,
+                    rtcp: false,
                 },
                 video_source: cam.clone(),
             }
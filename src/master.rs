@@ -32,6 +32,14 @@ pub fn run() {
 
     debug!("streams: {:#?}", streams);
 
+    // Count this run for the streams that will be started, until they play
+    let enabled_streams: Vec<String> = streams
+        .iter()
+        .filter(|stream| stream.enabled)
+        .map(|stream| stream.name.clone())
+        .collect();
+    settings::manager::add_unhealthy_run(&enabled_streams);
+
     for stream in streams {
         stream::manager::add_stream_and_start(stream).unwrap_or_else(|error| {
             error!("Not possible to start stream: {}", error.to_string());
@@ -1 +1,119 @@
+use log::*;
+use simple_error::SimpleError;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
 
+pub type MavlinkConnection =
+    Arc<Box<dyn mavlink::MavConnection<mavlink::common::MavMessage> + Sync + Send>>;
+
+// Validate a MAVLink connection string and convert it to the format used by the mavlink crate.
+// Accepted formats: serial:<PATH>:<BAUDRATE>, udpin|udpout|udpbcast:<HOST>:<PORT>
+// and tcp|tcpin|tcpout:<HOST>:<PORT>, where tcp is the same as tcpout.
+pub fn connection_string(endpoint: &str) -> Result<String, SimpleError> {
+    let (kind, address) = match endpoint.find(':') {
+        Some(position) => (&endpoint[..position], &endpoint[position + 1..]),
+        None => {
+            return Err(SimpleError::new(format!(
+                "Invalid MAVLink connection string, expected <TYPE>:<ADDRESS>: {}",
+                endpoint
+            )))
+        }
+    };
+
+    // Both address formats have the last field after the last colon
+    let (first, last) = match address.rfind(':') {
+        Some(position) => (&address[..position], &address[position + 1..]),
+        None => ("", ""),
+    };
+
+    match kind {
+        "serial" => {
+            if first.is_empty() || !last.parse::<u32>().map_or(false, |baud| baud > 0) {
+                return Err(SimpleError::new(format!(
+                    "Invalid MAVLink serial connection, expected serial:<PATH>:<BAUDRATE>: {}",
+                    endpoint
+                )));
+            }
+            return Ok(endpoint.to_string());
+        }
+        "udpin" | "udpout" | "udpbcast" | "tcp" | "tcpin" | "tcpout" => {
+            if first.is_empty() || last.parse::<u16>().is_err() {
+                return Err(SimpleError::new(format!(
+                    "Invalid MAVLink {} connection, expected {}:<HOST>:<PORT>: {}",
+                    kind, kind, endpoint
+                )));
+            }
+
+            // The mavlink crate panics if the address can't be resolved
+            if let Err(error) = address.to_socket_addrs() {
+                return Err(SimpleError::new(format!(
+                    "Failed to resolve MAVLink address {}: {}",
+                    address, error
+                )));
+            }
+
+            let kind = if kind == "tcp" { "tcpout" } else { kind };
+            return Ok(format!("{}:{}", kind, address));
+        }
+        _ => {
+            return Err(SimpleError::new(format!(
+                "Unsupported MAVLink connection type '{}', use serial, udpin, udpout, udpbcast, tcp, tcpin or tcpout: {}",
+                kind, endpoint
+            )));
+        }
+    }
+}
+
+// Connect to the MAVLink endpoint, None is returned if it is not possible
+pub fn connect(endpoint: &str) -> Option<MavlinkConnection> {
+    let connection_string = match connection_string(endpoint) {
+        Ok(connection_string) => connection_string,
+        Err(error) => {
+            error!("{}", error);
+            return None;
+        }
+    };
+
+    match mavlink::connect(&connection_string) {
+        Ok(connection) => Some(Arc::new(connection)),
+        Err(error) => {
+            error!(
+                "Failed to connect with MAVLink endpoint {}: {}",
+                endpoint, error
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_strings() {
+        assert_eq!(
+            connection_string("serial:/dev/ttyUSB0:57600").unwrap(),
+            "serial:/dev/ttyUSB0:57600"
+        );
+        assert_eq!(
+            connection_string("udpin:0.0.0.0:14550").unwrap(),
+            "udpin:0.0.0.0:14550"
+        );
+        assert_eq!(
+            connection_string("udpout:127.0.0.1:14550").unwrap(),
+            "udpout:127.0.0.1:14550"
+        );
+        assert_eq!(
+            connection_string("tcp:127.0.0.1:5760").unwrap(),
+            "tcpout:127.0.0.1:5760"
+        );
+
+        assert!(connection_string("serial:/dev/ttyUSB0").is_err());
+        assert!(connection_string("serial:/dev/ttyUSB0:fast").is_err());
+        assert!(connection_string("udpout:127.0.0.1").is_err());
+        assert!(connection_string("udpout:127.0.0.1:99999").is_err());
+        assert!(connection_string("potato:127.0.0.1:14550").is_err());
+        assert!(connection_string("127.0.0.1:14550").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use super::manager::{self, MavlinkConnection};
 use crate::cli;
 use crate::network;
 use crate::settings;
@@ -9,6 +10,9 @@ use url::Url;
 
 use std::sync::{Arc, Mutex};
 
+const MIN_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
 lazy_static! {
     static ref ID_CONTROL: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(vec![]));
 }
@@ -32,7 +36,10 @@ pub struct MavlinkCameraInformation {
     mavlink_connection_string: String,
     video_stream_uri: Url,
     video_source_type: VideoSourceType,
-    vehicle: Arc<Box<dyn mavlink::MavConnection<mavlink::common::MavMessage> + Sync + Send>>,
+    // Sent as the camera model, the source name is used if not set
+    description: Option<String>,
+    // None while disconnected, the receive message loop takes care of reconnecting
+    vehicle: Option<MavlinkConnection>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -63,39 +70,44 @@ impl std::fmt::Debug for MavlinkCameraInformation {
     }
 }
 
-impl Default for MavlinkCameraComponent {
-    fn default() -> Self {
+impl MavlinkCameraComponent {
+    // Each camera uses the first component ID available after the configured one
+    fn try_new() -> Result<Self, SimpleError> {
         let mut vector = ID_CONTROL.as_ref().lock().unwrap();
 
         // Find the closer ID available
-        let mut id: u8 = 0;
-        loop {
-            if vector.contains(&id) {
-                id += 1;
-                continue;
-            } else {
-                vector.push(id);
-                break;
-            }
-        }
-
-        Self {
-            system_id: 1,
-            component_id: mavlink::common::MavComponent::MAV_COMP_ID_CAMERA as u8 + id,
+        let id = (0..=u8::MAX)
+            .find(|id| !vector.contains(id))
+            .ok_or_else(|| SimpleError::new("No MAVLink component ID available"))?;
+        let component_id = cli::manager::mavlink_component_id()
+            .checked_add(id)
+            .ok_or_else(|| {
+                SimpleError::new(format!(
+                    "MAVLink component ID {} plus camera index {} is above {}",
+                    cli::manager::mavlink_component_id(),
+                    id,
+                    u8::MAX
+                ))
+            })?;
+        vector.push(id);
+
+        Ok(Self {
+            system_id: cli::manager::mavlink_system_id(),
+            component_id,
 
             vendor_name: Default::default(),
             model_name: Default::default(),
             firmware_version: 0,
             resolution_h: 0.0,
             resolution_v: 0.0,
-        }
+        })
     }
 }
 
 impl Drop for MavlinkCameraComponent {
     fn drop(&mut self) {
         // Remove id from used ids
-        let id = self.component_id - mavlink::common::MavComponent::MAV_COMP_ID_CAMERA as u8;
+        let id = self.component_id - cli::manager::mavlink_component_id();
         let mut vector = ID_CONTROL.as_ref().lock().unwrap();
         let position = vector.iter().position(|&vec_id| vec_id == id).unwrap();
         vector.remove(position);
@@ -105,21 +117,27 @@ impl Drop for MavlinkCameraComponent {
 impl MavlinkCameraInformation {
     fn new(
         video_source_type: VideoSourceType,
+        description: Option<String>,
         mavlink_connection_string: &str,
         video_stream_uri: Url,
-    ) -> Self {
-        Self {
-            component: Default::default(),
+    ) -> Result<Self, SimpleError> {
+        Ok(Self {
+            component: MavlinkCameraComponent::try_new()?,
             mavlink_connection_string: mavlink_connection_string.into(),
             video_stream_uri,
             video_source_type,
-            vehicle: Arc::new(mavlink::connect(&mavlink_connection_string).unwrap()),
-        }
+            description,
+            vehicle: manager::connect(mavlink_connection_string),
+        })
     }
 }
 
 impl MavlinkCameraHandle {
-    pub fn new(video_source_type: VideoSourceType, endpoint: Url) -> Self {
+    pub fn new(
+        video_source_type: VideoSourceType,
+        description: Option<String>,
+        endpoint: Url,
+    ) -> Result<Self, SimpleError> {
         debug!(
             "Starting new MAVLink camera device for: {:#?}, endpoint: {}",
             video_source_type, endpoint
@@ -128,9 +146,10 @@ impl MavlinkCameraHandle {
         let mavlink_camera_information: Arc<Mutex<MavlinkCameraInformation>> =
             Arc::new(Mutex::new(MavlinkCameraInformation::new(
                 video_source_type,
+                description,
                 &settings::manager::mavlink_endpoint(),
                 endpoint,
-            )));
+            )?));
 
         let thread_state = Arc::new(Mutex::new(ThreadState::RUNNING));
 
@@ -140,7 +159,7 @@ impl MavlinkCameraHandle {
         let heartbeat_state = thread_state.clone();
         let receive_message_state = thread_state.clone();
 
-        Self {
+        Ok(Self {
             mavlink_camera_information: mavlink_camera_information.clone(),
             thread_state: thread_state.clone(),
             heartbeat_thread: std::thread::spawn(move || {
@@ -152,7 +171,7 @@ impl MavlinkCameraHandle {
                     receive_message_mavlink_information,
                 )
             }),
-        }
+        })
     }
 }
 
@@ -168,11 +187,10 @@ fn heartbeat_loop(
     mavlink_camera_information: Arc<Mutex<MavlinkCameraInformation>>,
 ) {
     let mut header = mavlink::MavHeader::default();
-    let mavlink_camera_information = mavlink_camera_information.as_ref().lock().unwrap();
-    header.system_id = mavlink_camera_information.component.system_id;
-    header.component_id = mavlink_camera_information.component.component_id;
-    let vehicle = mavlink_camera_information.vehicle.clone();
-    drop(mavlink_camera_information);
+    let information = mavlink_camera_information.as_ref().lock().unwrap();
+    header.system_id = information.component.system_id;
+    header.component_id = information.component.component_id;
+    drop(information);
 
     loop {
         std::thread::sleep(std::time::Duration::from_secs(1));
@@ -193,9 +211,17 @@ fn heartbeat_loop(
             continue;
         }
 
-        debug!("sending heartbeat");
-        if let Err(error) = vehicle.as_ref().send(&header, &heartbeat_message()) {
-            error!("Failed to send heartbeat: {:?}", error);
+        let vehicle = mavlink_camera_information
+            .as_ref()
+            .lock()
+            .unwrap()
+            .vehicle
+            .clone();
+        if let Some(vehicle) = vehicle {
+            debug!("sending heartbeat");
+            if let Err(error) = vehicle.send(&header, &heartbeat_message()) {
+                error!("Failed to send heartbeat: {:?}", error);
+            }
         }
     }
 }
@@ -208,22 +234,58 @@ fn receive_message_loop(
     let information = mavlink_camera_information.as_ref().lock().unwrap();
     header.system_id = information.component.system_id;
     header.component_id = information.component.component_id;
-
-    let vehicle = information.vehicle.clone();
     drop(information);
-    let vehicle = vehicle.as_ref();
+
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
     loop {
         let loop_state = atomic_thread_state.as_ref().lock().unwrap().clone();
         if loop_state == ThreadState::DEAD {
             break;
         }
 
+        let vehicle = mavlink_camera_information
+            .as_ref()
+            .lock()
+            .unwrap()
+            .vehicle
+            .clone();
+        let vehicle = match vehicle {
+            Some(vehicle) => vehicle,
+            None => {
+                // Wait more between each failure to avoid flooding with reconnections
+                std::thread::sleep(reconnect_delay);
+                reconnect_delay = std::cmp::min(reconnect_delay * 2, MAX_RECONNECT_DELAY);
+
+                // The connection blocks, the information is not locked while it's done
+                let connection_string = mavlink_camera_information
+                    .as_ref()
+                    .lock()
+                    .unwrap()
+                    .mavlink_connection_string
+                    .clone();
+                info!("Reconnecting with MAVLink endpoint: {}", connection_string);
+                let vehicle = manager::connect(&connection_string);
+                mavlink_camera_information.as_ref().lock().unwrap().vehicle = vehicle;
+                continue;
+            }
+        };
+
         match vehicle.recv() {
-            Ok((_header, msg)) => {
+            Ok((their_header, msg)) => {
+                reconnect_delay = MIN_RECONNECT_DELAY;
                 match msg {
                     // Check if there is any camera information request from gcs
                     mavlink::common::MavMessage::COMMAND_LONG(command_long) => {
-                        match command_long.command {
+                        // Commands should be for this camera or broadcasted
+                        let for_us = (command_long.target_system == 0
+                            || command_long.target_system == header.system_id)
+                            && (command_long.target_component == 0
+                                || command_long.target_component == header.component_id);
+                        if !for_us {
+                            continue;
+                        }
+
+                        let result = match command_long.command {
                             mavlink::common::MavCmd::MAV_CMD_REQUEST_CAMERA_INFORMATION => {
                                 debug!("Sending camera_information..");
                                 let information =
@@ -231,6 +293,8 @@ fn receive_message_loop(
                                 let source_string =
                                     information.video_source_type.inner().source_string();
                                 let vendor_name = information.video_source_type.inner().name();
+                                let model_name =
+                                    information.description.as_ref().unwrap_or(vendor_name);
 
                                 let ips = network::utils::get_ipv4_addresses();
                                 let visible_qgc_ip_address = &ips.last().unwrap().to_string();
@@ -239,19 +303,21 @@ fn receive_message_loop(
                                     &header,
                                     &camera_information(
                                         vendor_name,
-                                        vendor_name,
+                                        model_name,
                                         visible_qgc_ip_address,
                                         source_string,
                                     ),
                                 ) {
                                     warn!("Failed to send camera_information: {:?}", error);
                                 }
+                                mavlink::common::MavResult::MAV_RESULT_ACCEPTED
                             }
                             mavlink::common::MavCmd::MAV_CMD_REQUEST_CAMERA_SETTINGS => {
                                 debug!("Sending camera_settings..");
                                 if let Err(error) = vehicle.send(&header, &camera_settings()) {
                                     warn!("Failed to send camera_settings: {:?}", error);
                                 }
+                                mavlink::common::MavResult::MAV_RESULT_ACCEPTED
                             }
                             mavlink::common::MavCmd::MAV_CMD_REQUEST_STORAGE_INFORMATION => {
                                 debug!("Sending camera_storage_information..");
@@ -260,6 +326,7 @@ fn receive_message_loop(
                                 {
                                     warn!("Failed to send camera_storage_information: {:?}", error);
                                 }
+                                mavlink::common::MavResult::MAV_RESULT_ACCEPTED
                             }
                             mavlink::common::MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS => {
                                 debug!("Sending camera_capture_status..");
@@ -267,6 +334,7 @@ fn receive_message_loop(
                                 {
                                     warn!("Failed to send camera_capture_status: {:?}", error);
                                 }
+                                mavlink::common::MavResult::MAV_RESULT_ACCEPTED
                             }
                             mavlink::common::MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_INFORMATION => {
                                 debug!("Sending video_stream_information..");
@@ -283,6 +351,14 @@ fn receive_message_loop(
                                 ) {
                                     warn!("Failed to send video_stream_information: {:?}", error);
                                 }
+                                mavlink::common::MavResult::MAV_RESULT_ACCEPTED
+                            }
+                            mavlink::common::MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_STATUS => {
+                                debug!("Sending video_stream_status..");
+                                if let Err(error) = vehicle.send(&header, &video_stream_status()) {
+                                    warn!("Failed to send video_stream_status: {:?}", error);
+                                }
+                                mavlink::common::MavResult::MAV_RESULT_ACCEPTED
                             }
                             _ => {
                                 let information =
@@ -291,7 +367,20 @@ fn receive_message_loop(
                                     "Camera: {:#?}, ignoring command: {:#?}",
                                     information, command_long.command
                                 );
+                                mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED
                             }
+                        };
+
+                        if let Err(error) = vehicle.send(
+                            &header,
+                            &command_ack(
+                                command_long.command,
+                                result,
+                                their_header.system_id,
+                                their_header.component_id,
+                            ),
+                        ) {
+                            warn!("Failed to send command_ack: {:?}", error);
                         }
                     }
                     mavlink::common::MavMessage::PARAM_EXT_SET(param_ext_set) => {
@@ -357,6 +446,12 @@ fn receive_message_loop(
                     }
                 }
             }
+            Err(mavlink::error::MessageReadError::Io(error)) => {
+                // The connection was lost, E.g: serial device unplugged
+                let mut information = mavlink_camera_information.as_ref().lock().unwrap();
+                error!("Camera: {:#?}, Connection error: {:#?}", information, error);
+                information.vehicle = None;
+            }
             Err(error) => {
                 let information = mavlink_camera_information.as_ref().lock().unwrap();
                 error!("Camera: {:#?}, Recv error: {:#?}", information, error);
@@ -418,24 +513,38 @@ fn heartbeat_message() -> mavlink::common::MavMessage {
     })
 }
 
+fn command_ack(
+    command: mavlink::common::MavCmd,
+    result: mavlink::common::MavResult,
+    target_system: u8,
+    target_component: u8,
+) -> mavlink::common::MavMessage {
+    mavlink::common::MavMessage::COMMAND_ACK(mavlink::common::COMMAND_ACK_DATA {
+        command,
+        result,
+        progress: 0,
+        result_param2: 0,
+        target_system,
+        target_component,
+    })
+}
+
+// Create a fixed size array with the name, longer names are truncated
+fn fixed_size_name(name: &str) -> [u8; 32] {
+    let mut fixed_size_name: [u8; 32] = [0; 32];
+    let length = name.len().min(fixed_size_name.len());
+    fixed_size_name[..length].copy_from_slice(&name.as_bytes()[..length]);
+    return fixed_size_name;
+}
+
 fn camera_information(
     vendor_name: &str,
     model_name: &str,
     http_server_address: &str,
     video_source_path: &str,
 ) -> mavlink::common::MavMessage {
-    // Create a fixed size array with the camera name
-    let name_str = String::from(vendor_name);
-    let mut vendor_name: [u8; 32] = [0; 32];
-    for index in 0..name_str.len() as usize {
-        vendor_name[index] = name_str.as_bytes()[index];
-    }
-
-    let name_str = String::from(model_name);
-    let mut model_name: [u8; 32] = [0; 32];
-    for index in 0..name_str.len() as usize {
-        model_name[index] = name_str.as_bytes()[index];
-    }
+    let vendor_name = fixed_size_name(vendor_name);
+    let model_name = fixed_size_name(model_name);
 
     // Send path to our camera configuration file
     let uri = format!(
@@ -535,3 +644,17 @@ fn video_stream_information(video_name: &str, video_uri: &str) -> mavlink::commo
         },
     )
 }
+
+fn video_stream_status() -> mavlink::common::MavMessage {
+    //The only important information here is the flags variable, everything else is fake
+    mavlink::common::MavMessage::VIDEO_STREAM_STATUS(mavlink::common::VIDEO_STREAM_STATUS_DATA {
+        framerate: 30.0,
+        bitrate: 1000,
+        flags: mavlink::common::VideoStreamStatusFlags::VIDEO_STREAM_STATUS_FLAGS_RUNNING,
+        resolution_h: 1000,
+        resolution_v: 1000,
+        rotation: 0,
+        hfov: 0,
+        stream_id: 1, // Starts at 1, 0 is for broadcast
+    })
+}
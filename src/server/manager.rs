@@ -27,11 +27,80 @@ pub fn run(server_address: &str) {
             .data(web::JsonConfig::default().error_handler(json_error_handler))
             .route("/", web::get().to(pages::root))
             .route(r"/{filename:.*(\.html|\.js)}", web::get().to(pages::root))
+            .route("/docs/openapi.json", web::get().to(pages::openapi))
             .route("/delete_stream", web::delete().to(pages::remove_stream))
             .route("/streams", web::get().to(pages::streams))
             .route("/streams", web::post().to(pages::streams_post))
+            .route("/streams/shed", web::post().to(pages::streams_shed))
+            .route("/streams/reload", web::post().to(pages::streams_reload))
+            .route("/streams/{name}", web::patch().to(pages::streams_patch))
+            .route(
+                "/streams/{name}/enable",
+                web::post().to(pages::streams_enable),
+            )
+            .route(
+                "/streams/{name}/disable",
+                web::post().to(pages::streams_disable),
+            )
+            .route("/streams/{name}/graph", web::get().to(pages::streams_graph))
+            .route("/streams/{name}/sdp", web::get().to(pages::streams_sdp))
+            .route(
+                "/streams/{name}/config",
+                web::get().to(pages::streams_config),
+            )
+            .route(
+                "/streams/{name}/flush",
+                web::post().to(pages::streams_flush),
+            )
+            .route(
+                "/streams/{name}/clone",
+                web::post().to(pages::streams_clone),
+            )
+            .route(
+                "/streams/{name}/udp/clients",
+                web::post().to(pages::streams_udp_clients_post),
+            )
+            .route(
+                "/streams/{name}/udp/clients",
+                web::delete().to(pages::streams_udp_clients_delete),
+            )
+            .route(
+                "/streams/{name}/priority",
+                web::get().to(pages::streams_priority),
+            )
+            .route(
+                "/streams/{name}/priority",
+                web::patch().to(pages::streams_priority_patch),
+            )
+            .route("/ws/streams", web::get().to(pages::ws_streams))
+            .route("/gst/elements", web::get().to(pages::gst_elements))
+            .route("/version", web::get().to(pages::version))
+            .route("/mavlink", web::get().to(pages::mavlink))
+            .route("/metrics", web::get().to(pages::metrics))
+            .route("/settings/profiles", web::get().to(pages::profiles))
+            .route(
+                "/settings/stream-defaults",
+                web::get().to(pages::stream_defaults),
+            )
+            .route(
+                "/settings/stream-defaults",
+                web::put().to(pages::stream_defaults_put),
+            )
+            .route(
+                "/settings/profiles/{name}",
+                web::post().to(pages::profiles_post),
+            )
+            .route(
+                "/settings/profiles/{name}/apply",
+                web::post().to(pages::profiles_apply),
+            )
             .route("/v4l", web::get().to(pages::v4l))
             .route("/v4l", web::post().to(pages::v4l_post))
+            .route("/v4l/auto", web::post().to(pages::v4l_auto))
+            .route("/v4l/batch", web::post().to(pages::v4l_batch))
+            .route("/v4l/snapshot", web::get().to(pages::v4l_snapshot))
+            .route("/v4l/snapshot", web::post().to(pages::v4l_snapshot_post))
+            .route("/source/formats", web::get().to(pages::source_formats))
             .route("/xml", web::get().to(pages::xml))
     })
     .bind(server_address)
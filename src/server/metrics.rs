@@ -0,0 +1,152 @@
+use crate::stream::types::StreamMetrics;
+
+const PREFIX: &str = "mavlink_camera_manager";
+
+// Label values are quoted, backslashes, quotes and new lines should be escaped
+fn label(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+}
+
+fn header(output: &mut String, name: &str, kind: &str, help: &str) {
+    output.push_str(&format!("# HELP {}_{} {}\n", PREFIX, name, help));
+    output.push_str(&format!("# TYPE {}_{} {}\n", PREFIX, name, kind));
+}
+
+// Render the streams state in the Prometheus text exposition format
+pub fn render(streams: &[StreamMetrics]) -> String {
+    let mut output = String::new();
+
+    header(
+        &mut output,
+        "streams",
+        "gauge",
+        "Number of configured streams.",
+    );
+    output.push_str(&format!("{}_streams {}\n", PREFIX, streams.len()));
+
+    header(
+        &mut output,
+        "streams_running",
+        "gauge",
+        "Number of running streams.",
+    );
+    output.push_str(&format!(
+        "{}_streams_running {}\n",
+        PREFIX,
+        streams.iter().filter(|stream| stream.running).count()
+    ));
+
+    header(
+        &mut output,
+        "stream_running",
+        "gauge",
+        "Whether the stream is running.",
+    );
+    for stream in streams {
+        output.push_str(&format!(
+            "{}_stream_running{{stream=\"{}\",sink=\"{}\"}} {}\n",
+            PREFIX,
+            label(&stream.name),
+            label(&stream.sink),
+            stream.running as u8
+        ));
+    }
+
+    header(
+        &mut output,
+        "stream_pipeline_state",
+        "gauge",
+        "Current state of the stream GStreamer pipeline.",
+    );
+    for stream in streams {
+        output.push_str(&format!(
+            "{}_stream_pipeline_state{{stream=\"{}\",sink=\"{}\",state=\"{}\"}} 1\n",
+            PREFIX,
+            label(&stream.name),
+            label(&stream.sink),
+            label(stream.pipeline_state.as_deref().unwrap_or("null"))
+        ));
+    }
+
+    header(
+        &mut output,
+        "stream_sent_bytes_total",
+        "counter",
+        "Bytes sent by the stream sinks since the pipeline started.",
+    );
+    for stream in streams {
+        if let Some(bytes_sent) = stream.bytes_sent {
+            output.push_str(&format!(
+                "{}_stream_sent_bytes_total{{stream=\"{}\",sink=\"{}\"}} {}\n",
+                PREFIX,
+                label(&stream.name),
+                label(&stream.sink),
+                bytes_sent
+            ));
+        }
+    }
+
+    header(
+        &mut output,
+        "stream_queue_overruns_total",
+        "counter",
+        "Number of times the stream pipeline queues got full, frames are dropped when it increases.",
+    );
+    for stream in streams {
+        output.push_str(&format!(
+            "{}_stream_queue_overruns_total{{stream=\"{}\",sink=\"{}\"}} {}\n",
+            PREFIX,
+            label(&stream.name),
+            label(&stream.sink),
+            stream.queue_overruns
+        ));
+    }
+
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = render(&[
+            StreamMetrics {
+                name: "Front \"camera\"".into(),
+                sink: "udp".into(),
+                running: true,
+                pipeline_state: Some("playing".into()),
+                bytes_sent: Some(4242),
+                queue_overruns: 3,
+            },
+            StreamMetrics {
+                name: "Recording".into(),
+                sink: "file".into(),
+                running: false,
+                pipeline_state: None,
+                bytes_sent: None,
+                queue_overruns: 0,
+            },
+        ]);
+
+        assert!(metrics.contains("mavlink_camera_manager_streams 2\n"));
+        assert!(metrics.contains("mavlink_camera_manager_streams_running 1\n"));
+        assert!(metrics.contains(
+            "mavlink_camera_manager_stream_running{stream=\"Front \\\"camera\\\"\",sink=\"udp\"} 1\n"
+        ));
+        assert!(metrics.contains(
+            "mavlink_camera_manager_stream_pipeline_state{stream=\"Recording\",sink=\"file\",state=\"null\"} 1\n"
+        ));
+        assert!(metrics.contains(
+            "mavlink_camera_manager_stream_sent_bytes_total{stream=\"Front \\\"camera\\\"\",sink=\"udp\"} 4242\n"
+        ));
+        assert!(!metrics.contains("stream_sent_bytes_total{stream=\"Recording\""));
+        assert!(metrics.contains(
+            "mavlink_camera_manager_stream_queue_overruns_total{stream=\"Recording\",sink=\"file\"} 0\n"
+        ));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod manager;
+mod metrics;
 mod pages;
+mod websocket;
@@ -1,13 +1,42 @@
-use crate::stream::types::StreamInformation;
+use super::{metrics, websocket};
+use crate::stream::{stream_backend, types::StreamInformation};
 use crate::video::{
-    types::{Control, Format, VideoSourceType},
-    video_source,
-    video_source::VideoSource,
-    xml,
+    types::{
+        AutoFeature, Control, ControlType, ControlValue, ControlsSnapshot, DeviceInformation,
+        Format, VideoEncodeType, VideoSourceType,
+    },
+    video_source, xml,
 };
+use crate::video_stream::types::{default_enabled, VideoAndStreamInformation};
 use actix_web::{web, HttpRequest, HttpResponse};
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
+
+#[derive(Debug, Serialize)]
+struct FlushResult {
+    // All pipeline elements accepted the flush and the keyframe request
+    accepted: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Version {
+    version: String,
+    git_sha: String,
+    build_date: String,
+    gstreamer: String,
+    // Plugins that provide the relevant elements, with their versions
+    plugins: BTreeMap<String, String>,
+}
+
+// Returned when the requested video source does not exist, so clients can pick another one
+#[derive(Debug, Serialize)]
+struct SourceNotFound {
+    error: String,
+    source: String,
+    sources_available: Vec<String>,
+}
 
 #[derive(Debug, Serialize)]
 struct ApiVideoSource {
@@ -15,6 +44,8 @@ struct ApiVideoSource {
     source: String,
     formats: Vec<Format>,
     controls: Vec<Control>,
+    // v4l2 driver information, only available for local sources
+    device: Option<DeviceInformation>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -22,15 +53,87 @@ pub struct V4lControl {
     device: String,
     v4l_id: u64,
     value: i64,
+    // The value is a percentage (0 to 100) of the slider range
+    #[serde(default)]
+    normalized: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct V4lRequest {
+    // Report the slider values as percentages of their range
+    #[serde(default)]
+    normalized: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct V4lControlResult {
+    control: V4lControl,
+    success: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct V4lAuto {
+    device: String,
+    feature: AutoFeature,
+    auto: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PostStream {
     name: String,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default)]
+    priority: u8,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    controls: Vec<ControlValue>,
     source: String,
     stream_information: StreamInformation,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct StreamsQuery {
+    running: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StreamPriority {
+    priority: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShedQuery {
+    count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShedResult {
+    stopped: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchStream {
+    endpoints: Vec<Url>,
+}
+
+// Fields of the cloned stream that replace the ones of the original stream,
+// streams can't share their source or endpoints
+#[derive(Debug, Deserialize)]
+pub struct CloneStream {
+    name: String,
+    source: String,
+    endpoints: Vec<Url>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UdpClient {
+    host: String,
+    port: u16,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RemoveStream {
     name: String,
@@ -38,7 +141,20 @@ pub struct RemoveStream {
 
 #[derive(Debug, Deserialize)]
 pub struct XmlFileRequest {
-    file: String,
+    // Video source string, E.g: /dev/video0
+    file: Option<String>,
+    // Video source name, used when the source string is not known
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotRequest {
+    device: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SourceRequest {
+    source: String,
 }
 
 pub fn load_file(file_name: &str) -> String {
@@ -74,31 +190,28 @@ pub fn root(req: HttpRequest) -> HttpResponse {
 }
 
 //TODO: change endpoint name to sources
-pub fn v4l(req: HttpRequest) -> HttpResponse {
-    debug!("{:#?}", req);
+pub fn v4l(req: HttpRequest, query: web::Query<V4lRequest>) -> HttpResponse {
+    debug!("{:#?}{:#?}", req, query);
 
-    let cameras = video_source::cameras_available();
+    let cameras = video_source::cached_capabilities();
     let cameras: Vec<serde_json::value::Value> = cameras
-        .iter()
-        .map(|cam| match cam {
-            VideoSourceType::Local(cam) => {
-                let camera = ApiVideoSource {
-                    name: cam.name().clone(),
-                    source: cam.source_string().to_string(),
-                    formats: cam.formats(),
-                    controls: cam.controls(),
-                };
-                serde_json::to_value(&camera).unwrap()
-            }
-            VideoSourceType::Gst(gst) => {
-                let camera = ApiVideoSource {
-                    name: gst.name().clone(),
-                    source: gst.source_string().to_string(),
-                    formats: gst.formats(),
-                    controls: gst.controls(),
-                };
-                serde_json::to_value(&camera).unwrap()
+        .into_iter()
+        .map(|mut cam| {
+            if query.normalized {
+                for control in &mut cam.controls {
+                    if let ControlType::Slider(slider) = &control.configuration {
+                        control.configuration = ControlType::Slider(slider.normalized());
+                    }
+                }
             }
+            let camera = ApiVideoSource {
+                name: cam.source.inner().name().clone(),
+                source: cam.source.inner().source_string().to_string(),
+                formats: cam.formats,
+                controls: cam.controls,
+                device: cam.device,
+            };
+            serde_json::to_value(&camera).unwrap()
         })
         .collect();
 
@@ -107,10 +220,20 @@ pub fn v4l(req: HttpRequest) -> HttpResponse {
         .body(serde_json::to_string_pretty(&cameras).unwrap())
 }
 
+fn set_control(control: &V4lControl) -> std::io::Result<()> {
+    let value = match control.normalized {
+        true => {
+            video_source::normalized_control_value(&control.device, control.v4l_id, control.value)?
+        }
+        false => control.value,
+    };
+    return video_source::set_control(&control.device, control.v4l_id, value);
+}
+
 pub fn v4l_post(req: HttpRequest, json: web::Json<V4lControl>) -> HttpResponse {
     debug!("{:#?}{:?}", req, json);
     let control = json.into_inner();
-    let answer = video_source::set_control(&control.device, control.v4l_id, control.value);
+    let answer = set_control(&control);
     if answer.is_ok() {
         return HttpResponse::Ok().finish();
     };
@@ -120,37 +243,347 @@ pub fn v4l_post(req: HttpRequest, json: web::Json<V4lControl>) -> HttpResponse {
         .body(format!("{:#?}", answer.err().unwrap()));
 }
 
+pub fn v4l_batch(req: HttpRequest, json: web::Json<Vec<V4lControl>>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, json);
+    // Failures do not stop the remaining controls from being applied
+    let results: Vec<V4lControlResult> = json
+        .into_inner()
+        .into_iter()
+        .map(|control| {
+            let answer = set_control(&control);
+            V4lControlResult {
+                control,
+                success: answer.is_ok(),
+                error: answer.err().map(|error| error.to_string()),
+            }
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&results).unwrap())
+}
+
+pub fn v4l_auto(req: HttpRequest, json: web::Json<V4lAuto>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, json);
+    let auto = json.into_inner();
+    if let Err(error) = video_source::set_auto(&auto.device, auto.feature, auto.auto) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{:#?}", error.to_string()));
+    }
+
+    return HttpResponse::Ok().finish();
+}
+
+pub fn v4l_snapshot(req: HttpRequest, query: web::Query<SnapshotRequest>) -> HttpResponse {
+    debug!("{:#?}{:#?}", req, query);
+
+    match video_source::controls_snapshot(&query.device) {
+        Ok(snapshot) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&snapshot).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn v4l_snapshot_post(
+    req: HttpRequest,
+    query: web::Query<SnapshotRequest>,
+    json: web::Json<ControlsSnapshot>,
+) -> HttpResponse {
+    debug!("{:#?}{:#?}{:?}", req, query, json);
+
+    match video_source::apply_controls_snapshot(&query.device, &json.controls) {
+        Ok(result) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&result).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
 pub fn streams(req: HttpRequest) -> HttpResponse {
     debug!("{:#?}", req);
     use crate::stream::manager as stream_manager;
-    let streams = stream_manager::streams();
+    let mut streams = stream_manager::streams();
+
+    // Invalid queries are ignored and all streams are returned
+    if let Ok(query) = web::Query::<StreamsQuery>::from_query(req.query_string()) {
+        if let Some(running) = query.running {
+            streams.retain(|stream| stream.running == running);
+        }
+    }
+
     HttpResponse::Ok()
         .content_type("application/json")
         .body(serde_json::to_string_pretty(&streams).unwrap())
 }
 
-pub fn streams_post(req: HttpRequest, json: web::Json<PostStream>) -> HttpResponse {
+// Add the default fields to the request, objects are merged field by field
+// and the fields of the request are kept
+fn merge_defaults(value: &mut serde_json::Value, defaults: &serde_json::Value) {
+    if let (Some(value), Some(defaults)) = (value.as_object_mut(), defaults.as_object()) {
+        for (key, default) in defaults {
+            match value.get_mut(key) {
+                Some(value) => merge_defaults(value, default),
+                None => {
+                    value.insert(key.clone(), default.clone());
+                }
+            }
+        }
+    }
+}
+
+pub async fn streams_post(req: HttpRequest, json: web::Json<serde_json::Value>) -> HttpResponse {
     debug!("{:#?}{:?}", req, json);
-    let json = json.into_inner();
+    use crate::settings::manager as settings_manager;
+
+    let mut body = json.into_inner();
+    if let Some(body) = body.as_object_mut() {
+        merge_defaults(
+            body.entry("stream_information")
+                .or_insert_with(|| serde_json::json!({})),
+            &settings_manager::stream_defaults(),
+        );
+    }
+    let json: PostStream = match serde_json::from_value(body) {
+        Ok(json) => json,
+        Err(error) => {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(format!("Invalid stream: {}", error));
+        }
+    };
     //json.
     //TODO: Move stream manager to absolute scope, check others places
+    if let VideoEncodeType::UNKNOWN(name) = &json.stream_information.configuration.encode {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(format!(
+                "Encode {} is not known, the known encodes are: {:?}.",
+                name,
+                VideoEncodeType::known()
+            ));
+    }
+
+    let video_source = match video_source::get_video_source(&json.source) {
+        Ok(video_source) => video_source,
+        Err(error) => return source_not_found(&json.source, error.to_string()),
+    };
+
+    return add_stream_and_wait(VideoAndStreamInformation {
+        name: json.name,
+        enabled: json.enabled,
+        priority: json.priority,
+        description: json.description,
+        controls: json.controls,
+        stream_information: json.stream_information,
+        video_source,
+    })
+    .await;
+}
+
+pub async fn streams_clone(req: HttpRequest, json: web::Json<CloneStream>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, json);
+    let json = json.into_inner();
     use crate::stream::manager as stream_manager;
-    use crate::video_stream::types::VideoAndStreamInformation;
+
+    let name = req.match_info().query("name");
+    // The configuration is used since the status of the stream hides the SRT passphrases
+    let original = match stream_manager::stream_configuration(name) {
+        Ok(original) => original,
+        Err(_) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("Stream {} does not exist.", name));
+        }
+    };
+
+    if stream_manager::stream_configuration(&json.name).is_ok() {
+        return HttpResponse::Conflict()
+            .content_type("text/plain")
+            .body(format!("Stream {} already exists.", json.name));
+    }
 
     let video_source = match video_source::get_video_source(&json.source) {
         Ok(video_source) => video_source,
+        Err(error) => return source_not_found(&json.source, error.to_string()),
+    };
+
+    return match cloned_stream(&original, json.name, video_source, json.endpoints) {
+        Ok(video_and_stream_information) => add_stream_and_wait(video_and_stream_information).await,
+        Err(error) => HttpResponse::Conflict()
+            .content_type("text/plain")
+            .body(error),
+    };
+}
+
+// Copy of the stream with a new name, source and endpoints, which can't be the ones of
+// the original stream. The controls belong to the original source, so they are not copied
+fn cloned_stream(
+    original: &VideoAndStreamInformation,
+    name: String,
+    video_source: VideoSourceType,
+    endpoints: Vec<Url>,
+) -> Result<VideoAndStreamInformation, String> {
+    let original_source = original.video_source.inner().source_string();
+    if video_source.inner().source_string() == original_source {
+        return Err(format!(
+            "Stream {} already uses the source {}, the copy needs another source.",
+            original.name, original_source
+        ));
+    }
+
+    let common_endpoint = endpoints.iter().find(|endpoint| {
+        original
+            .stream_information
+            .endpoints
+            .iter()
+            .any(|other| stream_backend::same_destination(endpoint, other))
+    });
+    if let Some(endpoint) = common_endpoint {
+        return Err(format!(
+            "Stream {} already uses the endpoint {}, the copy needs other endpoints.",
+            original.name, endpoint
+        ));
+    }
+
+    return Ok(VideoAndStreamInformation {
+        name,
+        controls: vec![],
+        stream_information: StreamInformation {
+            endpoints,
+            ..original.stream_information.clone()
+        },
+        video_source,
+        ..original.clone()
+    });
+}
+
+fn source_not_found(source: &str, error: String) -> HttpResponse {
+    return HttpResponse::NotFound()
+        .content_type("application/json")
+        .body(
+            serde_json::to_string_pretty(&SourceNotFound {
+                error,
+                source: source.to_string(),
+                sources_available: video_source::sources_available(),
+            })
+            .unwrap(),
+        );
+}
+
+// Streams that can't start are not added instead of being kept without working,
+// the wait runs in the thread pool to not block the server
+async fn add_stream_and_wait(
+    video_and_stream_information: VideoAndStreamInformation,
+) -> HttpResponse {
+    use crate::stream::manager as stream_manager;
+
+    let timeout = crate::cli::manager::stream_startup_timeout();
+    let result = web::block(move || {
+        stream_manager::add_stream_and_wait_playing(video_and_stream_information, timeout)
+    })
+    .await;
+    if let Err(error) = result {
+        let error = match error {
+            actix_web::error::BlockingError::Error(error) => error.to_string(),
+            actix_web::error::BlockingError::Canceled => {
+                "Stream startup wait was canceled.".to_string()
+            }
+        };
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{:#?}", error));
+    }
+
+    return HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&stream_manager::streams()).unwrap());
+}
+
+pub fn streams_patch(req: HttpRequest, json: web::Json<PatchStream>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, json);
+    //TODO: Move stream manager to absolute scope, check others places
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::update_stream_endpoints(name, json.into_inner().endpoints) {
+        Ok(_) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&stream_manager::streams()).unwrap()),
         Err(error) => {
             return HttpResponse::NotAcceptable()
                 .content_type("text/plain")
                 .body(format!("{:#?}", error.to_string()));
         }
+    }
+}
+
+pub fn streams_udp_clients_post(req: HttpRequest, json: web::Json<UdpClient>) -> HttpResponse {
+    return change_udp_client(req, json.into_inner(), true);
+}
+
+pub fn streams_udp_clients_delete(req: HttpRequest, json: web::Json<UdpClient>) -> HttpResponse {
+    return change_udp_client(req, json.into_inner(), false);
+}
+
+fn change_udp_client(req: HttpRequest, client: UdpClient, add: bool) -> HttpResponse {
+    debug!("{:#?}{:?}", req, client);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    let stream = stream_manager::streams()
+        .into_iter()
+        .find(|stream| stream.video_and_stream.name == name);
+    match stream {
+        Some(stream) => {
+            let endpoints = &stream.video_and_stream.stream_information.endpoints;
+            if endpoints.first().map(|endpoint| endpoint.scheme()) != Some("udp") {
+                return HttpResponse::Conflict()
+                    .content_type("text/plain")
+                    .body(format!("Stream {} is not a UDP stream.", name));
+            }
+        }
+        None => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("Stream {} does not exist.", name));
+        }
+    }
+
+    // IPv6 addresses should be inside brackets
+    let host = if client.host.contains(':') {
+        format!("[{}]", client.host)
+    } else {
+        client.host
+    };
+    let endpoint = match Url::parse(&format!("udp://{}:{}", host, client.port)) {
+        Ok(endpoint) => endpoint,
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!(
+                    "Invalid UDP client {}:{}: {}",
+                    host, client.port, error
+                ));
+        }
     };
 
-    match stream_manager::add_stream_and_start(VideoAndStreamInformation {
-        name: json.name,
-        stream_information: json.stream_information,
-        video_source,
-    }) {
+    let result = if add {
+        stream_manager::add_udp_client(name, endpoint)
+    } else {
+        stream_manager::remove_udp_client(name, endpoint)
+    };
+    match result {
         Ok(_) => HttpResponse::Ok()
             .content_type("application/json")
             .body(serde_json::to_string_pretty(&stream_manager::streams()).unwrap()),
@@ -162,6 +595,313 @@ pub fn streams_post(req: HttpRequest, json: web::Json<PostStream>) -> HttpRespon
     }
 }
 
+pub fn streams_graph(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::stream_graph(name) {
+        Ok(Some(graph)) => HttpResponse::Ok()
+            .content_type("text/vnd.graphviz")
+            .body(graph),
+        Ok(None) => {
+            return HttpResponse::Conflict()
+                .content_type("text/plain")
+                .body(format!("Stream {} does not have a running pipeline.", name));
+        }
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+// Persisted configuration of a stream, used by clients to edit it
+pub fn streams_config(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::stream_configuration(name) {
+        Ok(video_and_stream_information) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&video_and_stream_information).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+// SDP file to play UDP streams, the packets are described as received by the first endpoint
+pub fn streams_sdp(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    if !stream_manager::streams()
+        .iter()
+        .any(|stream| stream.video_and_stream.name == name)
+    {
+        return HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!("Stream {} does not exist.", name));
+    }
+
+    match stream_manager::stream_sdp(name) {
+        Ok(Some(sdp)) => HttpResponse::Ok()
+            .content_type("application/sdp")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.sdp\"", name),
+            )
+            .body(sdp),
+        Ok(None) => {
+            return HttpResponse::Conflict()
+                .content_type("text/plain")
+                .body(format!("Stream {} is not a UDP stream.", name));
+        }
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn streams_flush(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::flush_stream(name) {
+        Ok(Some(accepted)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&FlushResult { accepted }).unwrap()),
+        Ok(None) => {
+            return HttpResponse::Conflict()
+                .content_type("text/plain")
+                .body(format!("Stream {} does not have a running pipeline.", name));
+        }
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+// Follow the streams state, all changes are sent as JSON events
+pub fn ws_streams(req: HttpRequest, payload: web::Payload) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    return websocket::response(&req, payload, stream_manager::subscribe());
+}
+
+// Streams state in the Prometheus text format, to be scraped by monitoring systems
+pub fn metrics(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    return HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render(&stream_manager::metrics()));
+}
+
+pub fn streams_priority(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::stream_priority(name) {
+        Ok(priority) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&StreamPriority { priority }).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn streams_priority_patch(req: HttpRequest, json: web::Json<StreamPriority>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, json);
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::set_stream_priority(name, json.priority) {
+        Ok(_) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&stream_manager::streams()).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn streams_shed(req: HttpRequest, query: web::Query<ShedQuery>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, query);
+    use crate::stream::manager as stream_manager;
+
+    let result = ShedResult {
+        stopped: stream_manager::shed_streams(query.count),
+    };
+    return HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&result).unwrap());
+}
+
+pub fn streams_enable(req: HttpRequest) -> HttpResponse {
+    return set_stream_enabled(req, true);
+}
+
+pub fn streams_disable(req: HttpRequest) -> HttpResponse {
+    return set_stream_enabled(req, false);
+}
+
+fn set_stream_enabled(req: HttpRequest, enabled: bool) -> HttpResponse {
+    debug!("{:#?}", req);
+    //TODO: Move stream manager to absolute scope, check others places
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    match stream_manager::set_stream_enabled(name, enabled) {
+        Ok(_) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&stream_manager::streams()).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn profiles(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::settings::manager as settings_manager;
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&settings_manager::profiles()).unwrap())
+}
+
+pub fn profiles_post(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::settings::manager as settings_manager;
+
+    let name = req.match_info().query("name");
+    settings_manager::set_profile(name, &settings_manager::streams());
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&settings_manager::profiles()).unwrap())
+}
+
+pub fn profiles_apply(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::settings::manager as settings_manager;
+    //TODO: Move stream manager to absolute scope, check others places
+    use crate::stream::manager as stream_manager;
+
+    let name = req.match_info().query("name");
+    let streams = match settings_manager::profiles().remove(name) {
+        Some(streams) => streams,
+        None => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("Profile {} does not exist.", name));
+        }
+    };
+
+    match stream_manager::apply_streams(streams) {
+        Ok(_) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&stream_manager::streams()).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+// Read the settings file again and change only the streams that are different
+pub fn streams_reload(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::settings::manager as settings_manager;
+    use crate::stream::manager as stream_manager;
+
+    if let Err(error) = settings_manager::load() {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{:#?}", error.to_string()));
+    }
+
+    match stream_manager::reload_streams(settings_manager::streams()) {
+        Ok(reload) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&reload).unwrap()),
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn stream_defaults(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::settings::manager as settings_manager;
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&settings_manager::stream_defaults()).unwrap())
+}
+
+pub fn stream_defaults_put(req: HttpRequest, json: web::Json<serde_json::Value>) -> HttpResponse {
+    debug!("{:#?}{:?}", req, json);
+    use crate::settings::manager as settings_manager;
+
+    let defaults = json.into_inner();
+    if !defaults.is_object() {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("Stream defaults should be an object with fields of the stream information.");
+    }
+
+    // The fields of the defaults should have the same types of the stream information ones,
+    // the required fields are added to check them
+    let mut stream_information = defaults.clone();
+    merge_defaults(
+        &mut stream_information,
+        &serde_json::json!({
+            "endpoints": ["udp://127.0.0.1:5600"],
+            "configuration": {
+                "encode": "H264",
+                "height": 480,
+                "width": 640,
+                "frame_interval": { "numerator": 1, "denominator": 30 }
+            }
+        }),
+    );
+    if let Err(error) = serde_json::from_value::<StreamInformation>(stream_information) {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(format!("Invalid stream defaults: {}", error));
+    }
+
+    settings_manager::set_stream_defaults(&defaults);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&defaults).unwrap())
+}
+
 pub fn remove_stream(req: HttpRequest, query: web::Query<RemoveStream>) -> HttpResponse {
     debug!("{:#?}{:?}", req, query);
     //TODO: Move stream manager to absolute scope, check others places
@@ -179,22 +919,412 @@ pub fn remove_stream(req: HttpRequest, query: web::Query<RemoveStream>) -> HttpR
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct MavlinkStatus {
+    endpoint: String,
+}
+
+pub fn mavlink(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::settings::manager as settings_manager;
+
+    let status = MavlinkStatus {
+        endpoint: settings_manager::mavlink_endpoint(),
+    };
+    return HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string_pretty(&status).unwrap());
+}
+
+pub fn gst_elements(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::gst::utils;
+
+    match utils::elements_information(utils::RELEVANT_ELEMENTS) {
+        Ok(elements) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&elements).unwrap()),
+        Err(error) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn version(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    use crate::stream::gst::utils;
+
+    let gstreamer = utils::gstreamer_version();
+    let plugins = utils::plugins_information(utils::RELEVANT_ELEMENTS);
+    match (gstreamer, plugins) {
+        (Ok(gstreamer), Ok(plugins)) => HttpResponse::Ok().content_type("application/json").body(
+            serde_json::to_string_pretty(&Version {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                git_sha: env!("VERGEN_GIT_SHA_SHORT").to_string(),
+                build_date: env!("VERGEN_BUILD_DATE").to_string(),
+                gstreamer,
+                plugins,
+            })
+            .unwrap(),
+        ),
+        (Err(error), _) | (_, Err(error)) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
+pub fn openapi(req: HttpRequest) -> HttpResponse {
+    debug!("{:#?}", req);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(std::include_str!("openapi.json"))
+}
+
+pub fn source_formats(req: HttpRequest, query: web::Query<SourceRequest>) -> HttpResponse {
+    debug!("{:#?}{:#?}", req, query);
+
+    match video_source::get_video_source(&query.source) {
+        Ok(video_source) => {
+            let camera = ApiVideoSource {
+                name: video_source.inner().name().clone(),
+                source: video_source.inner().source_string().to_string(),
+                formats: video_source.inner().formats(),
+                controls: video_source.inner().controls(),
+                device: video_source::device_information(&video_source),
+            };
+            return HttpResponse::Ok()
+                .content_type("application/json")
+                .body(serde_json::to_string_pretty(&camera).unwrap());
+        }
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{:#?}", error.to_string()));
+        }
+    }
+}
+
 pub fn xml(xml_file_request: web::Query<XmlFileRequest>) -> HttpResponse {
     debug!("{:#?}", xml_file_request);
-    let cameras = video_source::cameras_available();
-    let camera = cameras
-        .iter()
-        .find(|source| source.inner().source_string() == xml_file_request.file);
+    let cameras = video_source::cached_capabilities();
+    let camera = match (&xml_file_request.file, &xml_file_request.name) {
+        (Some(file), _) => cameras
+            .iter()
+            .find(|camera| camera.source.inner().source_string() == file),
+        (None, Some(name)) => cameras
+            .iter()
+            .find(|camera| camera.source.inner().name() == name),
+        (None, None) => {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body("Either file or name should be provided.");
+        }
+    };
 
     if let Some(camera) = camera {
         return HttpResponse::Ok()
             .content_type("text/xml")
-            .body(xml::from_video_source(camera.inner()));
+            .body(xml::from_controls(
+                camera.source.inner().name(),
+                &camera.controls,
+            ));
     }
+    let names: Vec<&str> = cameras
+        .iter()
+        .map(|camera| camera.source.inner().name().as_str())
+        .collect();
     return HttpResponse::NotFound()
         .content_type("text/plain")
         .body(format!(
-            "File for {} does not exist.",
-            xml_file_request.file
+            "File for {} does not exist, the available sources are: {:?}.",
+            xml_file_request
+                .file
+                .as_ref()
+                .or_else(|| xml_file_request.name.as_ref())
+                .unwrap(),
+            names
         ));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_clone() {
+        use crate::video::{
+            types::{CaptureConfiguration, FrameInterval, PixelFormat},
+            video_source_gst::{VideoSourceGst, VideoSourceGstType},
+            video_source_local::{VideoSourceLocal, VideoSourceLocalType},
+        };
+
+        let original = VideoAndStreamInformation {
+            name: "Test".into(),
+            enabled: true,
+            priority: 0,
+            description: None,
+            controls: vec![ControlValue {
+                control_id: 9963776,
+                value: 42,
+            }],
+            stream_information: StreamInformation {
+                endpoints: vec![Url::parse("udp://192.168.2.2:5600").unwrap()],
+                configuration: CaptureConfiguration {
+                    encode: VideoEncodeType::H264,
+                    height: 720,
+                    width: 1280,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                    keyframe_interval: None,
+                    encoder_threads: None,
+                    b_frames: None,
+                    pixel_format: PixelFormat::I420,
+                    allow_closest_resolution: false,
+                    io_mode: None,
+                },
+                udp_buffer_size: None,
+                multicast_ttl: None,
+                config_interval: None,
+                segment_duration: None,
+                ssrc: None,
+                seqnum_offset: None,
+                timestamp_offset: None,
+                rtp_mtu: None,
+                overlay: None,
+                crop: None,
+                rtcp: false,
+            },
+            video_source: VideoSourceType::Local(VideoSourceLocal {
+                name: "PotatoCam".into(),
+                device_path: "/dev/video42".into(),
+                typ: VideoSourceLocalType::Unknown("TestPotatoCam".into()),
+            }),
+        };
+        let fake_source = VideoSourceType::Gst(VideoSourceGst {
+            name: "Fake".into(),
+            source: VideoSourceGstType::Fake("ball".into()),
+        });
+        let endpoints = |endpoint: &str| vec![Url::parse(endpoint).unwrap()];
+
+        let copy = cloned_stream(
+            &original,
+            "Copy".into(),
+            fake_source.clone(),
+            endpoints("udp://192.168.2.2:5602"),
+        )
+        .unwrap();
+        assert_eq!(copy.name, "Copy");
+        assert_eq!(copy.video_source, fake_source);
+        assert_eq!(
+            copy.stream_information.endpoints,
+            endpoints("udp://192.168.2.2:5602")
+        );
+        assert_eq!(
+            copy.stream_information.configuration,
+            original.stream_information.configuration
+        );
+        // The controls belong to the original source
+        assert!(copy.controls.is_empty());
+
+        // Streams can't share the source or the endpoints
+        assert!(cloned_stream(
+            &original,
+            "Copy".into(),
+            original.video_source.clone(),
+            endpoints("udp://192.168.2.2:5602"),
+        )
+        .is_err());
+        assert!(cloned_stream(
+            &original,
+            "Copy".into(),
+            fake_source,
+            endpoints("udp://192.168.2.2:5600?pt=97"),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn stream_defaults_merge() {
+        let mut stream_information = serde_json::json!({
+            "endpoints": ["udp://192.168.2.2:5600"],
+            "configuration": { "width": 1280, "frame_interval": { "denominator": 15 } },
+            "rtp_mtu": null
+        });
+        merge_defaults(
+            &mut stream_information,
+            &serde_json::json!({
+                "endpoints": ["udp://192.168.2.1:5600"],
+                "configuration": {
+                    "encode": "H264",
+                    "height": 480,
+                    "width": 640,
+                    "frame_interval": { "numerator": 1, "denominator": 30 }
+                },
+                "rtp_mtu": 1200,
+                "config_interval": -1
+            }),
+        );
+
+        assert_eq!(
+            stream_information,
+            serde_json::json!({
+                "endpoints": ["udp://192.168.2.2:5600"],
+                "configuration": {
+                    "encode": "H264",
+                    "height": 480,
+                    "width": 1280,
+                    "frame_interval": { "numerator": 1, "denominator": 15 }
+                },
+                "rtp_mtu": null,
+                "config_interval": -1
+            })
+        );
+    }
+
+    #[test]
+    fn streams_post_unknown_encode() {
+        let post: serde_json::Value = serde_json::from_str(
+            r#"{
+                "name": "Test",
+                "source": "/dev/video42",
+                "stream_information": {
+                    "endpoints": ["udp://192.168.2.2:5600"],
+                    "configuration": {
+                        "encode": "H266",
+                        "height": 720,
+                        "width": 1280,
+                        "frame_interval": { "numerator": 1, "denominator": 30 }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_value::<PostStream>(post.clone())
+                .unwrap()
+                .stream_information
+                .configuration
+                .encode,
+            VideoEncodeType::UNKNOWN("H266".into())
+        );
+
+        let request = actix_web::test::TestRequest::default().to_http_request();
+        let response =
+            actix_web::rt::System::new("test").block_on(streams_post(request, web::Json(post)));
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn streams_post_missing_source() {
+        let post = serde_json::json!({
+            "name": "Test",
+            "source": "/dev/potato",
+            "stream_information": {
+                "endpoints": ["udp://192.168.2.2:5600"],
+                "configuration": {
+                    "encode": "H264",
+                    "height": 720,
+                    "width": 1280,
+                    "frame_interval": { "numerator": 1, "denominator": 30 }
+                }
+            }
+        });
+
+        let request = actix_web::test::TestRequest::default().to_http_request();
+        let response =
+            actix_web::rt::System::new("test").block_on(streams_post(request, web::Json(post)));
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let body = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => bytes.clone(),
+            body => panic!("Unexpected body: {:?}", body),
+        };
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["source"], "/dev/potato");
+        assert!(body["sources_available"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("ball")));
+    }
+
+    #[test]
+    fn streams_clone_missing_stream() {
+        let clone = CloneStream {
+            name: "Copy".into(),
+            source: "ball".into(),
+            endpoints: vec![Url::parse("udp://192.168.2.2:5602").unwrap()],
+        };
+
+        let request = actix_web::test::TestRequest::default()
+            .param("name", "Potato")
+            .to_http_request();
+        let response =
+            actix_web::rt::System::new("test").block_on(streams_clone(request, web::Json(clone)));
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn streams_sdp_missing_stream() {
+        let request = actix_web::test::TestRequest::default()
+            .param("name", "Potato")
+            .to_http_request();
+        let response = streams_sdp(request);
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn streams_config_missing_stream() {
+        let request = actix_web::test::TestRequest::default()
+            .param("name", "Potato")
+            .to_http_request();
+        let response = streams_config(request);
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn openapi_is_valid_json() {
+        let openapi: serde_json::Value =
+            serde_json::from_str(std::include_str!("openapi.json")).unwrap();
+        let paths = openapi["paths"].as_object().unwrap();
+        for path in &[
+            "/streams",
+            "/streams/{name}",
+            "/streams/{name}/enable",
+            "/streams/{name}/disable",
+            "/streams/{name}/graph",
+            "/streams/{name}/clone",
+            "/streams/{name}/config",
+            "/streams/{name}/sdp",
+            "/streams/{name}/flush",
+            "/streams/{name}/priority",
+            "/streams/{name}/udp/clients",
+            "/streams/shed",
+            "/streams/reload",
+            "/ws/streams",
+            "/delete_stream",
+            "/v4l",
+            "/v4l/auto",
+            "/v4l/batch",
+            "/v4l/snapshot",
+            "/source/formats",
+            "/xml",
+            "/docs/openapi.json",
+            "/gst/elements",
+            "/version",
+            "/mavlink",
+            "/metrics",
+            "/settings/profiles",
+            "/settings/stream-defaults",
+            "/settings/profiles/{name}",
+            "/settings/profiles/{name}/apply",
+        ] {
+            assert!(paths.contains_key(*path), "Missing path: {}", path);
+        }
+    }
+}
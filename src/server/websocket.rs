@@ -0,0 +1,64 @@
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use futures::channel::mpsc;
+use log::*;
+
+// Send each message as a text frame, the messages of the client are ignored
+struct MessagesSocket {
+    messages: Option<mpsc::UnboundedReceiver<String>>,
+}
+
+impl Actor for MessagesSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        if let Some(messages) = self.messages.take() {
+            context.add_stream(messages);
+        }
+    }
+}
+
+// The connection is closed when there are no more messages to send
+impl StreamHandler<String> for MessagesSocket {
+    fn handle(&mut self, message: String, context: &mut Self::Context) {
+        context.text(message);
+    }
+}
+
+// Answer the control frames sent by the client
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MessagesSocket {
+    fn handle(
+        &mut self,
+        message: Result<ws::Message, ws::ProtocolError>,
+        context: &mut Self::Context,
+    ) {
+        match message {
+            Ok(ws::Message::Ping(payload)) => context.pong(&payload),
+            Ok(ws::Message::Close(reason)) => {
+                context.close(reason);
+                context.stop();
+            }
+            Ok(_) => {}
+            Err(error) => {
+                warn!("WebSocket protocol error: {}", error);
+                context.stop();
+            }
+        }
+    }
+}
+
+// Upgrade the request to a WebSocket that sends each message as a text frame
+pub fn response(
+    req: &HttpRequest,
+    payload: web::Payload,
+    messages: mpsc::UnboundedReceiver<String>,
+) -> HttpResponse {
+    let socket = MessagesSocket {
+        messages: Some(messages),
+    };
+    return match ws::start(socket, req, payload) {
+        Ok(response) => response,
+        Err(error) => HttpResponse::from_error(error),
+    };
+}
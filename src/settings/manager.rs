@@ -1,10 +1,12 @@
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io::prelude::*;
 use std::sync::{Arc, Mutex};
 
 use crate::cli;
 use crate::video_stream::types::VideoAndStreamInformation;
+use simple_error::SimpleError;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct HeaderSettingsFile {
@@ -17,6 +19,20 @@ pub struct SettingsStruct {
     pub header: HeaderSettingsFile,
     pub mavlink_endpoint: String, //TODO: Move to URL
     pub streams: Vec<VideoAndStreamInformation>,
+    // Named sets of streams that can be applied later
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Vec<VideoAndStreamInformation>>,
+    // Number of runs in a row where each stream was started and did not play,
+    // streams that played in their last run are not present
+    #[serde(default)]
+    pub unhealthy_streams: BTreeMap<String, u32>,
+    // Fields of the stream information used by new streams that do not set them
+    #[serde(default = "default_stream_defaults")]
+    pub stream_defaults: serde_json::Value,
+}
+
+fn default_stream_defaults() -> serde_json::Value {
+    return serde_json::json!({});
 }
 
 #[derive(Debug)]
@@ -29,6 +45,10 @@ struct Manager {
     pub content: Option<ManagerStruct>,
 }
 
+// Version of the settings file layout, should be increased when the layout changes
+// and a migration added to the migrate function if older files can't be read anymore
+const SETTINGS_VERSION: u32 = 20;
+
 lazy_static! {
     static ref MANAGER: Arc<Mutex<Manager>> = Arc::new(Mutex::new(Manager { content: None }));
 }
@@ -38,11 +58,15 @@ impl Default for SettingsStruct {
         SettingsStruct {
             header: HeaderSettingsFile {
                 name: "Camera Manager".to_string(),
-                version: 0,
+                version: SETTINGS_VERSION,
             },
             mavlink_endpoint: "udpout:0.0.0.0:14550".to_string(),
             streams: vec![/*VideoAndStreamInformation {
                 name: "Test".into(),
+                enabled: true,
+                priority: 0,
+                description: None,
+                controls: vec![],
                 stream_information: StreamInformation {
                     endpoints: vec![Url::parse("udp://0.0.0.0:5601").unwrap()],
                     configuration: CaptureConfiguration {
@@ -53,7 +77,24 @@ impl Default for SettingsStruct {
                             numerator: 1,
                             denominator: 30,
                         },
+                        keyframe_interval: None,
+                        encoder_threads: None,
+                        b_frames: None,
+                        pixel_format: PixelFormat::I420,
+                        allow_closest_resolution: false,
+                        io_mode: None,
                     },
+                    udp_buffer_size: None,
+                    multicast_ttl: None,
+                    config_interval: None,
+                    segment_duration: None,
+                    ssrc: None,
+                    seqnum_offset: None,
+                    timestamp_offset: None,
+                    rtp_mtu: None,
+                    overlay: None,
+                    crop: None,
+                    rtcp: false,
                 },
                 video_source: VideoSourceType::Local(VideoSourceLocal {
                     name: "Camera Manager Default Camera".into(),
@@ -65,6 +106,9 @@ impl Default for SettingsStruct {
                     }),
                 }),
             }*/],
+            profiles: BTreeMap::new(),
+            unhealthy_streams: BTreeMap::new(),
+            stream_defaults: default_stream_defaults(),
         }
     }
 }
@@ -130,20 +174,79 @@ fn load_settings_from_file(file_name: &str) -> SettingsStruct {
         return SettingsStruct::default();
     };
 
-    return serde_json::from_str(&result.unwrap().as_str())
-        .unwrap_or_else(|_error| SettingsStruct::default());
+    return settings_from_str(&result.unwrap());
 }
 
-//TODO: remove allow dead code
-#[allow(dead_code)]
-fn load() {
-    let mut manager = MANAGER.as_ref().lock().unwrap();
-    //TODO: deal with load problems
-    if let Some(content) = &mut manager.content {
-        content.config = load_settings_from_file(&content.file_name);
-    } else {
-        error!("Failed to load settings!");
+fn settings_from_str(content: &str) -> SettingsStruct {
+    return parse_settings(content).unwrap_or_else(|error| {
+        warn!("{}, using default.", error);
+        SettingsStruct::default()
+    });
+}
+
+fn parse_settings(content: &str) -> Result<SettingsStruct, String> {
+    let mut settings: serde_json::Value = match serde_json::from_str(content) {
+        Ok(settings) => settings,
+        Err(error) => return Err(format!("Failed to parse settings: {:#?}", error)),
+    };
+
+    migrate(&mut settings);
+
+    return serde_json::from_value(settings)
+        .map_err(|error| format!("Failed to load settings: {:#?}", error));
+}
+
+// Upgrade settings from older layouts to the current one
+fn migrate(settings: &mut serde_json::Value) {
+    let version = settings
+        .pointer("/header/version")
+        .and_then(|version| version.as_u64())
+        .unwrap_or(0);
+
+    if version > SETTINGS_VERSION as u64 {
+        warn!(
+            "Settings version {} is newer than the supported version {}, loading it anyway.",
+            version, SETTINGS_VERSION
+        );
+        return;
+    }
+
+    // Fields added to the layout so far are filled by their serde defaults,
+    // migrations are only needed when older fields are changed or removed
+
+    if version != SETTINGS_VERSION as u64 {
+        info!(
+            "Settings migrated from version {} to {}.",
+            version, SETTINGS_VERSION
+        );
     }
+
+    if let Some(version) = settings.pointer_mut("/header/version") {
+        *version = SETTINGS_VERSION.into();
+    }
+}
+
+// Read the settings file again to use the changes done by other tools,
+// the current settings are kept if the file can't be used
+pub fn load() -> Result<(), SimpleError> {
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let content = match &mut manager.content {
+        Some(content) => content,
+        None => return Err(SimpleError::new("Settings are not loaded.")),
+    };
+
+    let file_content = match std::fs::read_to_string(&content.file_name) {
+        Ok(file_content) => file_content,
+        Err(error) => {
+            return Err(SimpleError::new(format!(
+                "Failed to read settings file {}: {}",
+                content.file_name, error
+            )))
+        }
+    };
+
+    content.config = parse_settings(&file_content).map_err(SimpleError::new)?;
+    return Ok(());
 }
 
 fn save_settings_to_file(file_name: &str, content: &SettingsStruct) -> std::io::Result<()> {
@@ -217,12 +320,97 @@ pub fn set_streams(streams: &Vec<VideoAndStreamInformation>) {
     save();
 }
 
+pub fn profiles() -> BTreeMap<String, Vec<VideoAndStreamInformation>> {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    let content = manager.content.as_ref();
+    return content.unwrap().config.profiles.clone();
+}
+
+pub fn set_profile(name: &str, streams: &Vec<VideoAndStreamInformation>) {
+    // Take care of scope mutex
+    {
+        let mut manager = MANAGER.lock().unwrap();
+        let content = manager.content.as_mut();
+        content
+            .unwrap()
+            .config
+            .profiles
+            .insert(name.to_string(), streams.clone());
+    }
+    save();
+}
+
+// Count a new run of the service for the started streams, they are unhealthy until they play
+pub fn add_unhealthy_run(names: &[String]) {
+    // Take care of scope mutex
+    {
+        let mut manager = MANAGER.lock().unwrap();
+        let content = match manager.content.as_mut() {
+            Some(content) => content,
+            None => return,
+        };
+        for name in names {
+            *content
+                .config
+                .unhealthy_streams
+                .entry(name.clone())
+                .or_insert(0) += 1;
+        }
+    }
+    save();
+}
+
+pub fn unhealthy_runs(name: &str) -> u32 {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    return match manager.content.as_ref() {
+        Some(content) => *content.config.unhealthy_streams.get(name).unwrap_or(&0),
+        None => 0,
+    };
+}
+
+// The settings file is only written when the stream was unhealthy
+pub fn reset_unhealthy_runs(name: &str) {
+    // Take care of scope mutex
+    {
+        let mut manager = MANAGER.lock().unwrap();
+        let content = match manager.content.as_mut() {
+            Some(content) => content,
+            None => return,
+        };
+        if content.config.unhealthy_streams.remove(name).is_none() {
+            return;
+        }
+    }
+    save();
+}
+
+// There are no defaults while the settings are not loaded
+pub fn stream_defaults() -> serde_json::Value {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    return match manager.content.as_ref() {
+        Some(content) => content.config.stream_defaults.clone(),
+        None => default_stream_defaults(),
+    };
+}
+
+pub fn set_stream_defaults(stream_defaults: &serde_json::Value) {
+    // Take care of scope mutex
+    {
+        let mut manager = MANAGER.lock().unwrap();
+        let content = manager.content.as_mut();
+        content.unwrap().config.stream_defaults = stream_defaults.clone();
+    }
+    save();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::stream::types::StreamInformation;
     use crate::video::{
-        types::{CaptureConfiguration, FrameInterval, VideoEncodeType, VideoSourceType},
+        types::{
+            CaptureConfiguration, FrameInterval, PixelFormat, VideoEncodeType, VideoSourceType,
+        },
         video_source_local::{UsbBus, VideoSourceLocal, VideoSourceLocalType},
     };
     use url::Url;
@@ -263,6 +451,10 @@ mod tests {
 
         let mut fake_streams = vec![VideoAndStreamInformation {
             name: "PotatoTestStream".into(),
+            enabled: true,
+            priority: 0,
+            description: None,
+            controls: vec![],
             stream_information: StreamInformation {
                 endpoints: vec![Url::parse("udp://potatohost:4242").unwrap()],
                 configuration: CaptureConfiguration {
@@ -273,7 +465,24 @@ mod tests {
                         numerator: 17,
                         denominator: 47,
                     },
+                    keyframe_interval: None,
+                    encoder_threads: None,
+                    b_frames: None,
+                    pixel_format: PixelFormat::I420,
+                    allow_closest_resolution: false,
+                    io_mode: None,
                 },
+                udp_buffer_size: None,
+                multicast_ttl: None,
+                config_interval: None,
+                segment_duration: None,
+                ssrc: None,
+                seqnum_offset: None,
+                timestamp_offset: None,
+                rtp_mtu: None,
+                overlay: None,
+                crop: None,
+                rtcp: false,
             },
             video_source: VideoSourceType::Local(VideoSourceLocal {
                 name: "Fale Potato Test Video Source Camera".into(),
@@ -288,6 +497,82 @@ mod tests {
         set_streams(&mut fake_streams.clone());
         assert_eq!(streams(), fake_streams);
 
+        set_profile("PotatoProfile", &fake_streams);
+        assert_eq!(profiles()["PotatoProfile"], fake_streams);
+
+        let names = vec!["PotatoTestStream".to_string()];
+        assert_eq!(unhealthy_runs("PotatoTestStream"), 0);
+        add_unhealthy_run(&names);
+        add_unhealthy_run(&names);
+        assert_eq!(unhealthy_runs("PotatoTestStream"), 2);
+        reset_unhealthy_runs("PotatoTestStream");
+        assert_eq!(unhealthy_runs("PotatoTestStream"), 0);
+
         save();
     }
+
+    #[test]
+    fn test_migrate_v0() {
+        let settings_v0 = r#"{
+            "header": { "name": "Camera Manager", "version": 0 },
+            "mavlink_endpoint": "udpout:0.0.0.0:14550",
+            "streams": [{
+                "name": "PotatoTestStream",
+                "stream_information": {
+                    "endpoints": ["udp://potatohost:4242"],
+                    "configuration": {
+                        "encode": "H264",
+                        "height": 666,
+                        "width": 444,
+                        "frame_interval": { "numerator": 17, "denominator": 47 }
+                    }
+                },
+                "video_source": { "Local": {
+                    "name": "Fale Potato Test Video Source Camera",
+                    "device_path": "/dev/potatovideo",
+                    "type": { "Usb": { "interface": "0420:08:47", "usb_hub": 42, "usb_port": 77 } }
+                }}
+            }]
+        }"#;
+
+        let settings = settings_from_str(settings_v0);
+        assert_eq!(settings.header.version, SETTINGS_VERSION);
+        assert_eq!(settings.streams.len(), 1);
+        assert!(settings.streams[0].enabled);
+        assert_eq!(settings.streams[0].priority, 0);
+        assert_eq!(settings.streams[0].description, None);
+        assert!(settings.streams[0].controls.is_empty());
+
+        let configuration = &settings.streams[0].stream_information.configuration;
+        assert_eq!(configuration.height, 666);
+        assert_eq!(configuration.keyframe_interval, None);
+        assert_eq!(configuration.encoder_threads, None);
+        assert_eq!(configuration.b_frames, None);
+        assert_eq!(configuration.pixel_format, PixelFormat::I420);
+        assert!(!configuration.allow_closest_resolution);
+        assert_eq!(configuration.io_mode, None);
+        assert_eq!(settings.streams[0].stream_information.udp_buffer_size, None);
+        assert_eq!(settings.streams[0].stream_information.multicast_ttl, None);
+        assert_eq!(settings.streams[0].stream_information.config_interval, None);
+        assert_eq!(
+            settings.streams[0].stream_information.segment_duration,
+            None
+        );
+        assert_eq!(settings.streams[0].stream_information.rtp_mtu, None);
+        assert_eq!(settings.streams[0].stream_information.overlay, None);
+        assert_eq!(settings.streams[0].stream_information.crop, None);
+        assert!(!settings.streams[0].stream_information.rtcp);
+    }
+
+    #[test]
+    fn test_parse_invalid_settings() {
+        assert!(parse_settings("{ \"header\": ").is_err());
+        assert!(
+            parse_settings(r#"{ "header": { "name": "Camera Manager", "version": 19 } }"#).is_err()
+        );
+        assert_eq!(
+            settings_from_str("").streams,
+            SettingsStruct::default().streams
+        );
+    }
 }
@@ -1 +1,2 @@
 pub mod pipeline_runner;
+pub mod utils;
@@ -0,0 +1,157 @@
+use glib::translate::ToGlib;
+use gstreamer;
+use gstreamer::prelude::*;
+use log::*;
+use serde::Serialize;
+use simple_error::SimpleError;
+use std::collections::BTreeMap;
+
+// Encoders, parsers and payloaders that are relevant to create streams
+pub const RELEVANT_ELEMENTS: &[&str] = &[
+    "x264enc",
+    "v4l2h264enc",
+    "omxh264enc",
+    "vaapih264enc",
+    "nvh264enc",
+    "nvv4l2h264enc",
+    "x265enc",
+    "v4l2h265enc",
+    "vaapih265enc",
+    "nvh265enc",
+    "jpegenc",
+    "h264parse",
+    "h265parse",
+    "rtph264pay",
+    "rtph265pay",
+    "rtpjpegpay",
+    "videorate",
+    "videoconvert",
+    "multiudpsink",
+    "rtpbin",
+    "mpegtsmux",
+    "srtsink",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum H264Encoder {
+    X264,
+    V4l2,
+    Omx,
+    NvV4l2,
+}
+
+// Hardware H264 encoders in order of preference
+const HARDWARE_H264_ENCODERS: &[(&str, H264Encoder)] = &[
+    ("nvv4l2h264enc", H264Encoder::NvV4l2),
+    ("v4l2h264enc", H264Encoder::V4l2),
+    ("omxh264enc", H264Encoder::Omx),
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ElementInformation {
+    pub present: bool,
+    pub rank: Option<i32>,
+}
+
+// Check in the GStreamer registry which elements are available and their ranks
+pub fn elements_information(
+    names: &[&str],
+) -> Result<BTreeMap<String, ElementInformation>, SimpleError> {
+    if let Err(error) = gstreamer::init() {
+        return Err(SimpleError::new(format!(
+            "Failed to init GStreamer: {}",
+            error
+        )));
+    }
+
+    return Ok(names
+        .iter()
+        .map(|name| {
+            let factory = gstreamer::ElementFactory::find(name);
+            let information = ElementInformation {
+                present: factory.is_some(),
+                rank: factory.map(|factory| factory.get_rank().to_glib()),
+            };
+            (name.to_string(), information)
+        })
+        .collect());
+}
+
+// Version of each plugin that provides any of the elements, elements that are not installed are skipped
+pub fn plugins_information(names: &[&str]) -> Result<BTreeMap<String, String>, SimpleError> {
+    if let Err(error) = gstreamer::init() {
+        return Err(SimpleError::new(format!(
+            "Failed to init GStreamer: {}",
+            error
+        )));
+    }
+
+    return Ok(names
+        .iter()
+        .filter_map(|name| gstreamer::ElementFactory::find(name)?.get_plugin())
+        .map(|plugin| {
+            (
+                plugin.get_plugin_name().to_string(),
+                plugin.get_version().to_string(),
+            )
+        })
+        .collect());
+}
+
+// Version of the GStreamer library used at runtime
+pub fn gstreamer_version() -> Result<String, SimpleError> {
+    if let Err(error) = gstreamer::init() {
+        return Err(SimpleError::new(format!(
+            "Failed to init GStreamer: {}",
+            error
+        )));
+    }
+
+    return Ok(gstreamer::version_string().to_string());
+}
+
+// Find the best H264 encoder available in the GStreamer registry,
+// x264enc is used if there is no hardware encoder
+pub fn available_h264_encoder() -> H264Encoder {
+    if let Err(error) = gstreamer::init() {
+        warn!(
+            "Failed to init GStreamer, using software encoder: {}",
+            error
+        );
+        return H264Encoder::X264;
+    }
+
+    for (name, encoder) in HARDWARE_H264_ENCODERS {
+        if gstreamer::ElementFactory::find(name).is_some() {
+            debug!("Using hardware H264 encoder: {}", name);
+            return encoder.clone();
+        }
+    }
+
+    return H264Encoder::X264;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_elements() {
+        let elements = elements_information(&["fakesink", "potatosink"]).unwrap();
+        assert!(elements["fakesink"].present);
+        assert_eq!(
+            elements["potatosink"],
+            ElementInformation {
+                present: false,
+                rank: None,
+            }
+        );
+    }
+
+    #[test]
+    fn core_plugins() {
+        let plugins = plugins_information(&["fakesink", "queue", "potatosink"]).unwrap();
+        assert_eq!(plugins.keys().collect::<Vec<_>>(), vec!["coreelements"]);
+        assert!(gstreamer_version().unwrap().starts_with("GStreamer "));
+    }
+}
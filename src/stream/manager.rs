@@ -1,21 +1,47 @@
 use super::types::*;
 use super::{stream_backend, stream_backend::StreamBackend};
+use crate::cli;
 use crate::mavlink::mavlink_camera::MavlinkCameraHandle;
 use crate::settings;
+use crate::video::{
+    types::{ControlValue, VideoSourceType},
+    video_source::{self, VideoSource},
+    video_source_local::VideoSourceLocal,
+};
 use crate::video_stream::types::VideoAndStreamInformation;
+use futures::channel::mpsc;
 use log::*;
 use simple_error::SimpleError;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
 
 struct Stream {
     stream_type: StreamType,
     video_and_stream_information: VideoAndStreamInformation,
-    mavlink_camera: MavlinkCameraHandle,
+    // Only streams that can be played by the ground station are advertised over MAVLink
+    mavlink_camera: Option<MavlinkCameraHandle>,
 }
 
 #[derive(Default)]
 struct Manager {
     pub streams: Vec<Stream>,
+    // Receivers of the stream events, serialized as JSON
+    subscribers: Vec<mpsc::UnboundedSender<String>>,
+    // Streams being added, their name, source and endpoints can't be used by other streams
+    pending: Vec<VideoAndStreamInformation>,
+}
+
+// Reservation of a stream being added, released when it's dropped
+struct PendingStream {
+    name: String,
+}
+
+impl Drop for PendingStream {
+    fn drop(&mut self) {
+        let mut manager = MANAGER.as_ref().lock().unwrap();
+        manager.pending.retain(|pending| pending.name != self.name);
+    }
 }
 
 lazy_static! {
@@ -30,81 +56,621 @@ pub fn init() {
 pub fn start() {
     let mut manager = MANAGER.as_ref().lock().unwrap();
     for stream in &mut manager.streams {
-        match &mut stream.stream_type {
-            StreamType::UDP(stream) => {
-                stream.start();
-            }
+        if !stream.video_and_stream_information.enabled {
+            continue;
         }
+        start_stream(
+            &mut stream.stream_type,
+            &stream.video_and_stream_information,
+        );
+    }
+}
+
+// Create the stream, failing if the pipeline uses elements that are not installed
+fn new_stream(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<StreamType, SimpleError> {
+    let mut stream_type = stream_backend::new(video_and_stream_information)?;
+    stream_backend::check_installed_elements(
+        video_and_stream_information,
+        &stream_type.inner().pipeline(),
+    )?;
+
+    let name = video_and_stream_information.name.clone();
+    stream_type
+        .mut_inner()
+        .set_state_callback(Box::new(move || notify_stream_changed(&name)));
+    return Ok(stream_type);
+}
+
+// Streams that did not play in the last runs of the service may be crashing it,
+// the current run is already counted when the streams are started with the service
+fn is_held_back(unhealthy_runs: u32) -> bool {
+    return unhealthy_runs > cli::manager::max_unhealthy_runs();
+}
+
+// Start the stream pipeline unless it keeps failing to play across restarts,
+// those streams are only started again when they are enabled
+fn start_stream(
+    stream_type: &mut StreamType,
+    video_and_stream_information: &VideoAndStreamInformation,
+) {
+    let unhealthy_runs = settings::manager::unhealthy_runs(&video_and_stream_information.name);
+    start_stream_with(stream_type, video_and_stream_information, unhealthy_runs);
+}
+
+fn start_stream_with(
+    stream_type: &mut StreamType,
+    video_and_stream_information: &VideoAndStreamInformation,
+    unhealthy_runs: u32,
+) {
+    let name = video_and_stream_information.name.clone();
+    if is_held_back(unhealthy_runs) {
+        warn!(
+            "Stream {} did not play in the last {} runs, it will not start until it is enabled again.",
+            name,
+            unhealthy_runs - 1
+        );
+        return;
     }
+
+    stream_type
+        .mut_inner()
+        .set_playing_callback(Box::new(move || {
+            settings::manager::reset_unhealthy_runs(&name)
+        }));
+    stream_type.mut_inner().start();
+}
+
+// Configure the local source with the stream controls, failures do not prevent the stream to start.
+// The manager should not be locked since the device is accessed
+fn apply_controls(video_and_stream_information: &VideoAndStreamInformation) {
+    apply_controls_with(video_and_stream_information, |source, control| {
+        source.set_control_by_id(control.control_id, control.value)
+    });
+}
+
+fn apply_controls_with(
+    video_and_stream_information: &VideoAndStreamInformation,
+    set_control: impl Fn(&VideoSourceLocal, &ControlValue) -> std::io::Result<()>,
+) {
+    let source = match &video_and_stream_information.video_source {
+        VideoSourceType::Local(source) => source,
+        _ => return,
+    };
+
+    for control in &video_and_stream_information.controls {
+        if let Err(error) = set_control(source, control) {
+            warn!(
+                "Failed to set control {} to {} on {} for stream {}: {}",
+                control.control_id,
+                control.value,
+                source.source_string(),
+                video_and_stream_information.name,
+                error
+            );
+        }
+    }
+    if !video_and_stream_information.controls.is_empty() {
+        video_source::invalidate_cache();
+    }
+}
+
+fn mavlink_camera(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Option<MavlinkCameraHandle> {
+    // We have an endpoint since we have passed the point of stream creation
+    let endpoint = video_and_stream_information
+        .stream_information
+        .endpoints
+        .first()
+        .unwrap()
+        .clone();
+    // Only UDP streams can be advertised as MAVLink video streams
+    if endpoint.scheme() == "file" || endpoint.scheme() == "srt" {
+        return None;
+    }
+
+    // The stream works without the MAVLink camera, it's only not advertised
+    return MavlinkCameraHandle::new(
+        video_and_stream_information.video_source.clone(),
+        video_and_stream_information.description.clone(),
+        endpoint,
+    )
+    .map_err(|error| {
+        error!(
+            "Failed to create MAVLink camera for stream {}: {}",
+            video_and_stream_information.name, error
+        )
+    })
+    .ok();
+}
+
+fn stream_status(stream: &Stream) -> StreamStatus {
+    let unhealthy_runs =
+        settings::manager::unhealthy_runs(&stream.video_and_stream_information.name);
+    let mut video_and_stream = stream.video_and_stream_information.clone();
+    video_and_stream.stream_information.endpoints = video_and_stream
+        .stream_information
+        .endpoints
+        .iter()
+        .map(stream_backend::redacted_endpoint)
+        .collect();
+
+    return StreamStatus {
+        running: stream.stream_type.inner().is_running(),
+        resolution: stream.stream_type.inner().resolution(),
+        negotiated_caps: stream.stream_type.inner().negotiated_caps(),
+        uris: match &stream.stream_type {
+            // UDP and SRT streams are sent to the clients, where they can be played
+            StreamType::UDP(_) | StreamType::SRT(_) => {
+                video_and_stream.stream_information.endpoints.clone()
+            }
+            // Recordings are only available locally
+            StreamType::FILE(_) => vec![],
+        },
+        queue_overruns: stream.stream_type.inner().queue_overruns(),
+        source_disconnected: stream.stream_type.inner().source_disconnected(),
+        consecutive_failures: stream.stream_type.inner().consecutive_failures(),
+        last_error: stream.stream_type.inner().last_error(),
+        healthy: unhealthy_runs == 0,
+        held_back: stream.video_and_stream_information.enabled && is_held_back(unhealthy_runs),
+        video_and_stream,
+        rtp: stream_backend::rtp_parameters(&stream.video_and_stream_information).unwrap_or(None),
+    };
 }
 
 pub fn streams() -> Vec<StreamStatus> {
     let manager = MANAGER.as_ref().lock().unwrap();
-    let status: Vec<StreamStatus> = manager
+    let status: Vec<StreamStatus> = manager.streams.iter().map(stream_status).collect();
+
+    return status;
+}
+
+pub fn metrics() -> Vec<StreamMetrics> {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    return manager
         .streams
         .iter()
-        .map(|stream| StreamStatus {
+        .map(|stream| StreamMetrics {
+            name: stream.video_and_stream_information.name.clone(),
+            sink: match stream.stream_type {
+                StreamType::UDP(_) => "udp".to_string(),
+                StreamType::FILE(_) => "file".to_string(),
+                StreamType::SRT(_) => "srt".to_string(),
+            },
             running: stream.stream_type.inner().is_running(),
-            video_and_stream: stream.video_and_stream_information.clone(),
+            pipeline_state: stream
+                .stream_type
+                .inner()
+                .pipeline_state()
+                .map(|state| format!("{:?}", state).to_lowercase()),
+            bytes_sent: stream.stream_type.inner().bytes_sent(),
+            queue_overruns: stream.stream_type.inner().queue_overruns(),
         })
         .collect();
+}
 
-    return status;
+// Receive the stream events, the first one contains the current state of all streams
+pub fn subscribe() -> mpsc::UnboundedReceiver<String> {
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let (sender, receiver) = mpsc::unbounded();
+    let event = StreamEvent::Streams {
+        streams: manager.streams.iter().map(stream_status).collect(),
+    };
+    if sender
+        .unbounded_send(serde_json::to_string(&event).unwrap())
+        .is_ok()
+    {
+        manager.subscribers.push(sender);
+    }
+    return receiver;
+}
+
+// Send the event to all subscribers, the ones that are gone are removed
+fn notify(manager: &mut Manager, event: StreamEvent) {
+    let message = serde_json::to_string(&event).unwrap();
+    manager
+        .subscribers
+        .retain(|subscriber| subscriber.unbounded_send(message.clone()).is_ok());
+}
+
+// Called by the pipeline thread when it starts, stops, fails or plays.
+// Streams are never dropped while the manager is locked, otherwise this would wait forever
+fn notify_stream_changed(name: &str) {
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let stream = match manager
+        .streams
+        .iter()
+        .find(|stream| stream.video_and_stream_information.name == name)
+    {
+        Some(stream) => stream,
+        // Streams being created are announced once they are added
+        None => return,
+    };
+
+    let event = StreamEvent::Updated {
+        stream: stream_status(stream),
+    };
+    notify(&mut manager, event);
+}
+
+// New streams can't use the name, source or endpoints of the other streams,
+// including the ones that are being added
+fn check_conflicts(
+    manager: &Manager,
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<(), SimpleError> {
+    let streams = manager
+        .streams
+        .iter()
+        .map(|stream| &stream.video_and_stream_information)
+        .chain(manager.pending.iter());
+    for stream in streams {
+        stream.conflicts_with(video_and_stream_information)?
+    }
+    return Ok(());
 }
 
+// Reserve the name, source and endpoints of the stream while it's added
+fn reserve_stream(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<PendingStream, SimpleError> {
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    check_conflicts(&manager, video_and_stream_information)?;
+    manager.pending.push(video_and_stream_information.clone());
+    return Ok(PendingStream {
+        name: video_and_stream_information.name.clone(),
+    });
+}
+
+// Add the stream and start it, used for the streams loaded from the settings
 pub fn add_stream_and_start(
     video_and_stream_information: VideoAndStreamInformation,
+) -> Result<(), SimpleError> {
+    return add_stream(video_and_stream_information, None);
+}
+
+// Add the stream once its pipeline is playing, so streams that can't start are not
+// saved or announced. Blocks until the pipeline plays or the timeout expires
+pub fn add_stream_and_wait_playing(
+    video_and_stream_information: VideoAndStreamInformation,
+    timeout: Duration,
+) -> Result<(), SimpleError> {
+    return add_stream(video_and_stream_information, Some(timeout));
+}
+
+fn add_stream(
+    video_and_stream_information: VideoAndStreamInformation,
+    playing_timeout: Option<Duration>,
 ) -> Result<(), SimpleError> {
     //TODO: Check if stream can handle caps
-    let mut manager = MANAGER.as_ref().lock().unwrap();
+    // Host names are resolved while creating the pipeline, the manager is not locked for that
+    let mut stream = new_stream(&video_and_stream_information)?;
 
-    for stream in manager.streams.iter() {
-        stream
-            .video_and_stream_information
-            .conflicts_with(&video_and_stream_information)?
-    }
+    // Other requests can't use the source while the controls are applied and the stream starts.
+    // The reservation is released after the manager, and before the stream if it's not added
+    let _reservation = reserve_stream(&video_and_stream_information)?;
 
-    let mut stream = stream_backend::new(&video_and_stream_information)?;
-    let endpoint = video_and_stream_information
-        .stream_information
-        .endpoints
-        .first()
-        .unwrap() // We have an endpoint since we have passed the point of stream creation
-        .clone();
+    // Controls are applied without locking the manager
+    if video_and_stream_information.enabled {
+        apply_controls(&video_and_stream_information);
+        start_stream(&mut stream, &video_and_stream_information);
 
-    stream.mut_inner().start();
+        // The stream is not in the manager while waiting, it's dropped if it does not play
+        if let Some(timeout) = playing_timeout {
+            wait_playing(&stream, &video_and_stream_information.name, timeout)?;
+        }
+    }
+
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    manager
+        .pending
+        .retain(|pending| pending.name != video_and_stream_information.name);
     manager.streams.push(Stream {
         stream_type: stream,
         video_and_stream_information: video_and_stream_information.clone(),
-        mavlink_camera: MavlinkCameraHandle::new(
-            video_and_stream_information.video_source.clone(),
-            endpoint,
-        ),
+        mavlink_camera: mavlink_camera(&video_and_stream_information),
     });
 
-    //TODO: Create function to update settings
-    let video_and_stream_informations = manager
-        .streams
-        .iter()
-        .map(|stream| stream.video_and_stream_information.clone())
-        .collect();
-    settings::manager::set_streams(&video_and_stream_informations);
+    let event = StreamEvent::Added {
+        stream: stream_status(manager.streams.last().unwrap()),
+    };
+    notify(&mut manager, event);
+    update_settings(&manager);
+    return Ok(());
+}
+
+// Wait for the stream pipeline to be playing, used to detect streams that can't start
+fn wait_playing(stream: &StreamType, name: &str, timeout: Duration) -> Result<(), SimpleError> {
+    if !stream.inner().wait_playing(timeout) {
+        return Err(SimpleError::new(format!(
+            "Stream {} did not start playing in {} seconds.",
+            name,
+            timeout.as_secs()
+        )));
+    }
     return Ok(());
 }
 
 pub fn remove_stream(stream_name: &str) -> Result<(), SimpleError> {
     let find_stream = |stream: &Stream| stream.video_and_stream_information.name == *stream_name;
 
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let index = match manager.streams.iter().position(find_stream) {
+        Some(index) => index,
+        None => {
+            return Err(SimpleError::new(
+                "Identification does not match any stream.",
+            ))
+        }
+    };
+
+    let stream = manager.streams.remove(index);
+    // Removed streams are not tracked anymore
+    settings::manager::reset_unhealthy_runs(stream_name);
+    notify(
+        &mut manager,
+        StreamEvent::Removed {
+            name: stream_name.to_string(),
+        },
+    );
+    update_settings(&manager);
+
+    // Dropping the stream waits for its pipeline, the manager is not locked for that
+    drop(manager);
+    drop(stream);
+    return Ok(());
+}
+
+// Change of the stream pipeline, prepared without locking the manager
+enum PipelineUpdate {
+    // Only the clients of the running UDP pipeline are changed
+    Clients {
+        pipeline: String,
+        clients: String,
+        rtcp_clients: Option<String>,
+    },
+    // The stream is replaced by a new one
+    Replace(StreamType),
+}
+
+// Change the endpoints of a stream, updating the running pipeline when possible
+pub fn update_stream_endpoints(stream_name: &str, endpoints: Vec<Url>) -> Result<(), SimpleError> {
+    let find_stream = |stream: &Stream| stream.video_and_stream_information.name == *stream_name;
+
+    let (previous, previous_pipeline, is_udp) = {
+        let manager = MANAGER.as_ref().lock().unwrap();
+        match manager.streams.iter().find(|stream| find_stream(stream)) {
+            Some(stream) => (
+                stream.video_and_stream_information.clone(),
+                stream.stream_type.inner().pipeline(),
+                matches!(stream.stream_type, StreamType::UDP(_)),
+            ),
+            None => {
+                return Err(SimpleError::new(
+                    "Identification does not match any stream.",
+                ))
+            }
+        }
+    };
+
+    let mut video_and_stream_information = previous.clone();
+    video_and_stream_information.stream_information.endpoints = endpoints;
+
+    // Host names are resolved while checking and creating the pipeline,
+    // the manager is not locked for that
+    stream_backend::check(&video_and_stream_information)?;
+    let pipeline = match video_and_stream_information.stream_information.endpoints[0].scheme() {
+        "udp" if is_udp => Some(stream_backend::create_udp_pipeline(
+            &video_and_stream_information,
+        )?),
+        _ => None,
+    };
+    let update = match pipeline {
+        // Only the clients of a running UDP pipeline can be changed in place
+        Some(pipeline) if only_clients_changed(&previous_pipeline, &pipeline) => {
+            let endpoints = &video_and_stream_information.stream_information.endpoints;
+            PipelineUpdate::Clients {
+                pipeline,
+                clients: stream_backend::udp_clients(endpoints)?,
+                rtcp_clients: match video_and_stream_information.stream_information.rtcp {
+                    true => Some(stream_backend::rtcp_clients(endpoints)?),
+                    false => None,
+                },
+            }
+        }
+        _ => PipelineUpdate::Replace(new_stream(&video_and_stream_information)?),
+    };
+
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let index = match manager.streams.iter().position(find_stream) {
+        Some(index) => index,
+        None => {
+            return Err(SimpleError::new(
+                "Identification does not match any stream.",
+            ))
+        }
+    };
+    if manager.streams[index].video_and_stream_information != previous {
+        return Err(SimpleError::new(
+            "Stream was changed while its endpoints were updated.",
+        ));
+    }
+
+    for (other_index, stream) in manager.streams.iter().enumerate() {
+        if other_index != index {
+            stream
+                .video_and_stream_information
+                .conflicts_with(&video_and_stream_information)?
+        }
+    }
+    for pending in manager.pending.iter() {
+        pending.conflicts_with(&video_and_stream_information)?
+    }
+
+    let stream = &mut manager.streams[index];
+    let mut previous_stream_type = None;
+    match update {
+        PipelineUpdate::Clients {
+            pipeline,
+            clients,
+            rtcp_clients,
+        } => {
+            if let StreamType::UDP(udp_stream) = &mut stream.stream_type {
+                udp_stream.set_clients(&clients, rtcp_clients.as_deref())?;
+                udp_stream.set_pipeline_description(&pipeline);
+            }
+        }
+        PipelineUpdate::Replace(stream_type) => {
+            // The previous pipeline is stopped first to release the device
+            stream.stream_type.mut_inner().stop();
+            previous_stream_type = Some(std::mem::replace(&mut stream.stream_type, stream_type));
+            if video_and_stream_information.enabled {
+                start_stream(&mut stream.stream_type, &video_and_stream_information);
+            }
+        }
+    }
+
+    // We have endpoints since both informations passed the checks
+    if previous.stream_information.endpoints[0]
+        != video_and_stream_information.stream_information.endpoints[0]
+    {
+        stream.mavlink_camera = mavlink_camera(&video_and_stream_information);
+    }
+    stream.video_and_stream_information = video_and_stream_information;
+
+    let event = StreamEvent::Updated {
+        stream: stream_status(&manager.streams[index]),
+    };
+    notify(&mut manager, event);
+    update_settings(&manager);
+
+    // Dropping the previous stream waits for its pipeline, the manager is not locked for that
+    drop(manager);
+    drop(previous_stream_type);
+    return Ok(());
+}
+
+// Both UDP pipeline descriptions only differ by their clients
+fn only_clients_changed(previous_pipeline: &str, pipeline: &str) -> bool {
+    let without_clients = |pipeline: &str| -> Vec<String> {
+        pipeline
+            .split(' ')
+            .filter(|token| !token.starts_with("clients="))
+            .map(String::from)
+            .collect()
+    };
+    return without_clients(previous_pipeline) == without_clients(pipeline);
+}
+
+// Add a receiver to a UDP stream, the running pipeline is only restarted if it needs to be changed
+pub fn add_udp_client(stream_name: &str, endpoint: Url) -> Result<(), SimpleError> {
+    return change_udp_client(stream_name, endpoint, true);
+}
+
+// Remove a receiver from a UDP stream, the running pipeline is only restarted if it needs to be changed
+pub fn remove_udp_client(stream_name: &str, endpoint: Url) -> Result<(), SimpleError> {
+    return change_udp_client(stream_name, endpoint, false);
+}
+
+fn change_udp_client(stream_name: &str, endpoint: Url, add: bool) -> Result<(), SimpleError> {
+    let endpoints = {
+        let manager = MANAGER.as_ref().lock().unwrap();
+        let stream = match manager
+            .streams
+            .iter()
+            .find(|stream| stream.video_and_stream_information.name == *stream_name)
+        {
+            Some(stream) => stream,
+            None => {
+                return Err(SimpleError::new(
+                    "Identification does not match any stream.",
+                ))
+            }
+        };
+        match stream.stream_type {
+            StreamType::UDP(_) => {}
+            _ => return Err(SimpleError::new("Stream is not a UDP stream.")),
+        }
+
+        udp_client_endpoints(
+            &stream
+                .video_and_stream_information
+                .stream_information
+                .endpoints,
+            endpoint,
+            add,
+        )?
+    };
+
+    return update_stream_endpoints(stream_name, endpoints);
+}
+
+// Endpoints of a stream after adding or removing a client
+fn udp_client_endpoints(
+    endpoints: &[Url],
+    endpoint: Url,
+    add: bool,
+) -> Result<Vec<Url>, SimpleError> {
+    // Clients are the same if they have the same host and port, whatever their options
+    let is_client = endpoints
+        .iter()
+        .any(|other| stream_backend::same_destination(other, &endpoint));
+    if add {
+        if is_client {
+            return Err(SimpleError::new(format!(
+                "Endpoint is already a client of the stream: {}",
+                endpoint
+            )));
+        }
+        let mut endpoints = endpoints.to_vec();
+        endpoints.push(endpoint);
+        return Ok(endpoints);
+    }
+
+    if !is_client {
+        return Err(SimpleError::new(format!(
+            "Endpoint is not a client of the stream: {}",
+            endpoint
+        )));
+    }
+    return Ok(endpoints
+        .iter()
+        .filter(|other| !stream_backend::same_destination(other, &endpoint))
+        .cloned()
+        .collect());
+}
+
+// Enable or disable a stream, disabled streams are stopped and not started on boot
+pub fn set_stream_enabled(stream_name: &str, enabled: bool) -> Result<(), SimpleError> {
+    let find_stream = |stream: &Stream| stream.video_and_stream_information.name == *stream_name;
+
+    // Controls are applied without locking the manager
+    if enabled {
+        apply_controls(&stream_configuration(stream_name)?);
+    }
+
     let mut manager = MANAGER.as_ref().lock().unwrap();
     match manager.streams.iter().position(find_stream) {
         Some(index) => {
-            manager.streams.remove(index);
-            let video_and_stream_informations = manager
-                .streams
-                .iter()
-                .map(|stream| stream.video_and_stream_information.clone())
-                .collect();
-            settings::manager::set_streams(&video_and_stream_informations);
+            let stream = &mut manager.streams[index];
+            stream.video_and_stream_information.enabled = enabled;
+            if enabled {
+                // Enabling the stream gives it another chance, even if it did not play before
+                settings::manager::reset_unhealthy_runs(stream_name);
+                start_stream(
+                    &mut stream.stream_type,
+                    &stream.video_and_stream_information,
+                );
+            } else {
+                stream.stream_type.mut_inner().stop();
+            }
+            let event = StreamEvent::Updated {
+                stream: stream_status(&manager.streams[index]),
+            };
+            notify(&mut manager, event);
+            update_settings(&manager);
             Ok(())
         }
         None => Err(SimpleError::new(
@@ -113,13 +679,517 @@ pub fn remove_stream(stream_name: &str) -> Result<(), SimpleError> {
     }
 }
 
+pub fn stream_priority(stream_name: &str) -> Result<u8, SimpleError> {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    match manager
+        .streams
+        .iter()
+        .find(|stream| stream.video_and_stream_information.name == *stream_name)
+    {
+        Some(stream) => Ok(stream.video_and_stream_information.priority),
+        None => Err(SimpleError::new(
+            "Identification does not match any stream.",
+        )),
+    }
+}
+
+// SDP description of the stream, None if the stream is not sent over RTP
+pub fn stream_sdp(stream_name: &str) -> Result<Option<String>, SimpleError> {
+    // Host names are resolved for the description, the manager is not locked for that
+    let video_and_stream_information = stream_configuration(stream_name)?;
+    return stream_backend::sdp(&video_and_stream_information);
+}
+
+// Configuration of the stream as it's persisted, without the runtime state
+pub fn stream_configuration(stream_name: &str) -> Result<VideoAndStreamInformation, SimpleError> {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    match manager
+        .streams
+        .iter()
+        .find(|stream| stream.video_and_stream_information.name == *stream_name)
+    {
+        Some(stream) => Ok(stream.video_and_stream_information.clone()),
+        None => Err(SimpleError::new(
+            "Identification does not match any stream.",
+        )),
+    }
+}
+
+pub fn set_stream_priority(stream_name: &str, priority: u8) -> Result<(), SimpleError> {
+    let find_stream = |stream: &Stream| stream.video_and_stream_information.name == *stream_name;
+
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    match manager.streams.iter().position(find_stream) {
+        Some(index) => {
+            manager.streams[index].video_and_stream_information.priority = priority;
+            let event = StreamEvent::Updated {
+                stream: stream_status(&manager.streams[index]),
+            };
+            notify(&mut manager, event);
+            update_settings(&manager);
+            Ok(())
+        }
+        None => Err(SimpleError::new(
+            "Identification does not match any stream.",
+        )),
+    }
+}
+
+// Stop the running streams with the lowest priority to release resources,
+// the streams are kept enabled and start again on the next boot.
+// Return the name of the stopped streams
+pub fn shed_streams(count: usize) -> Vec<String> {
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let mut running: Vec<usize> = (0..manager.streams.len())
+        .filter(|&index| manager.streams[index].stream_type.inner().is_running())
+        .collect();
+    running.sort_by_key(|&index| manager.streams[index].video_and_stream_information.priority);
+
+    let mut stopped = vec![];
+    for index in running.into_iter().take(count) {
+        manager.streams[index].stream_type.mut_inner().stop();
+        let name = manager.streams[index]
+            .video_and_stream_information
+            .name
+            .clone();
+        info!("Stream {} stopped to release resources.", name);
+        let event = StreamEvent::Updated {
+            stream: stream_status(&manager.streams[index]),
+        };
+        notify(&mut manager, event);
+        stopped.push(name);
+    }
+
+    return stopped;
+}
+
+// Flush the running pipeline of the stream, None if it's not running
+pub fn flush_stream(stream_name: &str) -> Result<Option<bool>, SimpleError> {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    match manager
+        .streams
+        .iter()
+        .find(|stream| stream.video_and_stream_information.name == *stream_name)
+    {
+        Some(stream) => Ok(stream.stream_type.inner().flush()),
+        None => Err(SimpleError::new(
+            "Identification does not match any stream.",
+        )),
+    }
+}
+
+// GraphViz representation of a stream pipeline, None if the stream has no running pipeline
+pub fn stream_graph(stream_name: &str) -> Result<Option<String>, SimpleError> {
+    let manager = MANAGER.as_ref().lock().unwrap();
+    match manager
+        .streams
+        .iter()
+        .find(|stream| stream.video_and_stream_information.name == *stream_name)
+    {
+        Some(stream) => Ok(stream.stream_type.inner().pipeline_graph()),
+        None => Err(SimpleError::new(
+            "Identification does not match any stream.",
+        )),
+    }
+}
+
+// Problems that prevent a set of streams to run together
+fn validate_streams(video_and_stream_informations: &[VideoAndStreamInformation]) -> Vec<String> {
+    let mut errors: Vec<String> = vec![];
+
+    for (index, video_and_stream_information) in video_and_stream_informations.iter().enumerate() {
+        if !video_and_stream_information.video_source.inner().is_valid() {
+            errors.push(format!(
+                "{}: Source is not valid: {}",
+                video_and_stream_information.name,
+                video_and_stream_information
+                    .video_source
+                    .inner()
+                    .source_string()
+            ));
+        }
+
+        for other in video_and_stream_informations.iter().skip(index + 1) {
+            if let Err(error) = video_and_stream_information.conflicts_with(other) {
+                errors.push(format!("{}: {}", video_and_stream_information.name, error));
+            }
+        }
+    }
+
+    return errors;
+}
+
+// Replace all streams by a new set of streams,
+// nothing is changed if any of the new streams is not valid
+pub fn apply_streams(
+    video_and_stream_informations: Vec<VideoAndStreamInformation>,
+) -> Result<(), SimpleError> {
+    replace_streams(video_and_stream_informations, false)?;
+    return Ok(());
+}
+
+// Change the streams to a new set of streams, only the streams that are different are touched,
+// nothing is changed if any of the new streams is not valid
+pub fn reload_streams(
+    mut video_and_stream_informations: Vec<VideoAndStreamInformation>,
+) -> Result<StreamsReload, SimpleError> {
+    // Local sources may be in a different path since the settings were saved
+    for video_and_stream_information in &mut video_and_stream_informations {
+        if let VideoSourceType::Local(source) = &mut video_and_stream_information.video_source {
+            if !source.update_device() {
+                error!("Source appears to be invalid or not found: {:#?}", source);
+            }
+        }
+    }
+
+    return replace_streams(video_and_stream_informations, true);
+}
+
+// Replace the streams by a new set of streams, the unchanged streams keep running
+// if keep_unchanged is set, all the others are stopped and created again
+fn replace_streams(
+    video_and_stream_informations: Vec<VideoAndStreamInformation>,
+    keep_unchanged: bool,
+) -> Result<StreamsReload, SimpleError> {
+    let mut errors = validate_streams(&video_and_stream_informations);
+
+    // The new streams are created without locking the manager
+    let current: Vec<VideoAndStreamInformation> = {
+        let manager = MANAGER.as_ref().lock().unwrap();
+        manager
+            .streams
+            .iter()
+            .map(|stream| stream.video_and_stream_information.clone())
+            .collect()
+    };
+
+    let mut reload = StreamsReload::default();
+    // New streams to be created, None for the ones that are kept
+    let mut streams = vec![];
+    if errors.is_empty() {
+        for video_and_stream_information in video_and_stream_informations {
+            let name = video_and_stream_information.name.clone();
+            let existing = current
+                .iter()
+                .find(|current| current.name == video_and_stream_information.name);
+            if keep_unchanged && existing == Some(&video_and_stream_information) {
+                reload.kept.push(name);
+                streams.push((None, video_and_stream_information));
+                continue;
+            }
+
+            match new_stream(&video_and_stream_information) {
+                Ok(stream_type) => {
+                    match existing {
+                        Some(_) => reload.restarted.push(name),
+                        None => reload.added.push(name),
+                    }
+                    streams.push((Some(stream_type), video_and_stream_information));
+                }
+                Err(error) => errors.push(format!("{}: {}", name, error)),
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(SimpleError::new(format!(
+            "Streams were not changed: {}",
+            errors.join(", ")
+        )));
+    }
+
+    // Controls are applied without locking the manager
+    for (stream_type, video_and_stream_information) in &streams {
+        if stream_type.is_some() && video_and_stream_information.enabled {
+            apply_controls(video_and_stream_information);
+        }
+    }
+
+    let mut manager = MANAGER.as_ref().lock().unwrap();
+    let changed = manager
+        .streams
+        .iter()
+        .map(|stream| &stream.video_and_stream_information)
+        .ne(current.iter());
+    if changed || !manager.pending.is_empty() {
+        return Err(SimpleError::new(
+            "Streams were not changed: streams were changed by another request.",
+        ));
+    }
+
+    // Stop the streams that are not kept first to release their devices
+    let mut kept_streams = vec![];
+    let mut previous_streams = vec![];
+    for mut stream in manager.streams.drain(..) {
+        let name = stream.video_and_stream_information.name.clone();
+        if reload.kept.contains(&name) {
+            kept_streams.push(stream);
+            continue;
+        }
+        stream.stream_type.mut_inner().stop();
+        previous_streams.push(stream);
+        // Removed and restarted streams are not tracked anymore
+        settings::manager::reset_unhealthy_runs(&name);
+        if !reload.restarted.contains(&name) {
+            reload.removed.push(name);
+        }
+    }
+
+    for (stream_type, video_and_stream_information) in streams {
+        let stream = match stream_type {
+            Some(mut stream_type) => {
+                if video_and_stream_information.enabled {
+                    start_stream(&mut stream_type, &video_and_stream_information);
+                }
+                Stream {
+                    stream_type,
+                    mavlink_camera: mavlink_camera(&video_and_stream_information),
+                    video_and_stream_information,
+                }
+            }
+            None => {
+                let index = kept_streams
+                    .iter()
+                    .position(|stream| {
+                        stream.video_and_stream_information.name
+                            == video_and_stream_information.name
+                    })
+                    .unwrap();
+                kept_streams.remove(index)
+            }
+        };
+        manager.streams.push(stream);
+    }
+
+    let event = StreamEvent::Streams {
+        streams: manager.streams.iter().map(stream_status).collect(),
+    };
+    notify(&mut manager, event);
+    update_settings(&manager);
+
+    // Dropping the previous streams waits for their pipelines, the manager is not locked for that
+    drop(manager);
+    drop(previous_streams);
+    return Ok(reload);
+}
+
+fn update_settings(manager: &Manager) {
+    let video_and_stream_informations = manager
+        .streams
+        .iter()
+        .map(|stream| stream.video_and_stream_information.clone())
+        .collect();
+    settings::manager::set_streams(&video_and_stream_informations);
+}
+
 //TODO: rework to use UML definition
 // Add a new pipeline string to run
 /*
 pub fn add(description: &'static str) {
-    let mut stream = VideoStreamUdp::default();
+    let mut stream = VideoStreamPipeline::default();
     stream.set_pipeline_description(description);
     let mut manager = MANAGER.as_ref().lock().unwrap();
     manager.streams.push(StreamType::UDP(stream));
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_clients_changed() {
+        let pipeline = "videotestsrc ! x264enc ! rtph264pay config-interval=10 pt=96 ! multiudpsink clients=192.168.0.1:5600";
+
+        assert!(only_clients_changed(pipeline, pipeline));
+        assert!(only_clients_changed(
+            pipeline,
+            "videotestsrc ! x264enc ! rtph264pay config-interval=10 pt=96 ! multiudpsink clients=192.168.0.1:5600,192.168.0.2:5600"
+        ));
+        // Multicast clients need the multicast properties of the sink
+        assert!(!only_clients_changed(
+            pipeline,
+            "videotestsrc ! x264enc ! rtph264pay config-interval=10 pt=96 ! multiudpsink clients=192.168.0.1:5600,239.0.0.1:5600 auto-multicast=true ttl-mc=1"
+        ));
+    }
+
+    fn test_stream(name: &str, device_path: &str) -> VideoAndStreamInformation {
+        use crate::video::{
+            types::{CaptureConfiguration, FrameInterval, PixelFormat, VideoEncodeType},
+            video_source_local::VideoSourceLocalType,
+        };
+
+        return VideoAndStreamInformation {
+            name: name.into(),
+            enabled: true,
+            priority: 0,
+            description: None,
+            controls: vec![],
+            stream_information: StreamInformation {
+                endpoints: vec![Url::parse("udp://192.168.0.1:5600").unwrap()],
+                configuration: CaptureConfiguration {
+                    encode: VideoEncodeType::H264,
+                    height: 720,
+                    width: 1280,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                    keyframe_interval: None,
+                    encoder_threads: None,
+                    b_frames: None,
+                    pixel_format: PixelFormat::I420,
+                    allow_closest_resolution: false,
+                    io_mode: None,
+                },
+                udp_buffer_size: None,
+                multicast_ttl: None,
+                config_interval: None,
+                segment_duration: None,
+                ssrc: None,
+                seqnum_offset: None,
+                timestamp_offset: None,
+                rtp_mtu: None,
+                overlay: None,
+                crop: None,
+                rtcp: false,
+            },
+            video_source: VideoSourceType::Local(VideoSourceLocal {
+                name: "PotatoCam".into(),
+                device_path: device_path.into(),
+                typ: VideoSourceLocalType::Unknown("TestPotatoCam".into()),
+            }),
+        };
+    }
+
+    #[test]
+    fn test_reserve_stream() {
+        let stream = test_stream("Reserved", "/dev/video43");
+        let reservation = reserve_stream(&stream).unwrap();
+
+        // The name, source and endpoints can't be used while the stream is added
+        assert!(reserve_stream(&stream).is_err());
+        assert!(reserve_stream(&test_stream("Other", "/dev/video43")).is_err());
+
+        drop(reservation);
+        assert!(reserve_stream(&stream).is_ok());
+    }
+
+    #[test]
+    fn test_start_stream_held_back() {
+        let stream = test_stream("Test", "/dev/video42");
+        let started = |unhealthy_runs: u32| {
+            let mut stream_type = stream_backend::new(&stream).unwrap();
+            start_stream_with(&mut stream_type, &stream, unhealthy_runs);
+            stream_type.inner().is_running()
+        };
+
+        // Only the current run of the service is counted
+        assert!(started(0));
+        assert!(started(1));
+        // The stream did not play in the last run, it's held back
+        assert!(!started(2));
+        assert!(!started(5));
+    }
+
+    #[test]
+    fn test_apply_controls() {
+        use crate::video::{
+            types::{CaptureConfiguration, FrameInterval, PixelFormat, VideoEncodeType},
+            video_source_local::VideoSourceLocalType,
+        };
+        use std::cell::RefCell;
+
+        // Stream as it's saved in the settings, with the controls of its source
+        let stream = VideoAndStreamInformation {
+            name: "Test".into(),
+            enabled: true,
+            priority: 0,
+            description: None,
+            controls: vec![
+                ControlValue {
+                    control_id: 9963776,
+                    value: 42,
+                },
+                ControlValue {
+                    control_id: 9963777,
+                    value: -1,
+                },
+            ],
+            stream_information: StreamInformation {
+                endpoints: vec![Url::parse("udp://192.168.0.1:5600").unwrap()],
+                configuration: CaptureConfiguration {
+                    encode: VideoEncodeType::H264,
+                    height: 720,
+                    width: 1080,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                    keyframe_interval: None,
+                    encoder_threads: None,
+                    b_frames: None,
+                    pixel_format: PixelFormat::I420,
+                    allow_closest_resolution: false,
+                    io_mode: None,
+                },
+                udp_buffer_size: None,
+                multicast_ttl: None,
+                config_interval: None,
+                segment_duration: None,
+                ssrc: None,
+                seqnum_offset: None,
+                timestamp_offset: None,
+                rtp_mtu: None,
+                overlay: None,
+                crop: None,
+                rtcp: false,
+            },
+            video_source: VideoSourceType::Local(VideoSourceLocal {
+                name: "PotatoCam".into(),
+                device_path: "/dev/video42".into(),
+                typ: VideoSourceLocalType::Unknown("TestPotatoCam".into()),
+            }),
+        };
+
+        // All controls are applied to the source, even after a failure
+        let applied = RefCell::new(vec![]);
+        apply_controls_with(&stream, |source, control| {
+            applied
+                .borrow_mut()
+                .push((source.device_path.clone(), control.clone()));
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "Potato"))
+        });
+        assert_eq!(
+            applied.into_inner(),
+            vec![
+                ("/dev/video42".to_string(), stream.controls[0].clone()),
+                ("/dev/video42".to_string(), stream.controls[1].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_udp_client_endpoints() {
+        let endpoints = vec![Url::parse("udp://192.168.0.1:5600").unwrap()];
+        let endpoint = Url::parse("udp://192.168.0.2:5600").unwrap();
+
+        let added = udp_client_endpoints(&endpoints, endpoint.clone(), true).unwrap();
+        assert_eq!(added, vec![endpoints[0].clone(), endpoint.clone()]);
+        assert!(udp_client_endpoints(&added, endpoint.clone(), true).is_err());
+
+        assert_eq!(
+            udp_client_endpoints(&added, endpoint.clone(), false).unwrap(),
+            endpoints
+        );
+        assert!(udp_client_endpoints(&endpoints, endpoint, false).is_err());
+
+        // Endpoints with options are found by their host and port
+        let endpoints = vec![Url::parse("udp://192.168.0.1:5600?pt=97").unwrap()];
+        let endpoint = Url::parse("udp://192.168.0.1:5600").unwrap();
+        assert!(udp_client_endpoints(&endpoints, endpoint.clone(), true).is_err());
+        assert!(udp_client_endpoints(&endpoints, endpoint, false)
+            .unwrap()
+            .is_empty());
+    }
+}
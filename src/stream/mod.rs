@@ -1,5 +1,5 @@
-mod gst;
+pub mod gst;
 pub mod manager;
 pub mod stream_backend;
 pub mod types;
-pub mod video_stream_udp;
+pub mod video_stream_pipeline;
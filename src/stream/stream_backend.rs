@@ -1,13 +1,21 @@
+use super::gst::utils::{self, H264Encoder};
 use super::types::*;
-use super::video_stream_udp::VideoStreamUdp;
+use super::video_stream_pipeline::VideoStreamPipeline;
+use crate::cli;
 use crate::video::{
-    types::{VideoEncodeType, VideoSourceType},
+    types::{
+        CaptureConfiguration, Format, FrameInterval, PixelFormat, Size, VideoEncodeType,
+        VideoSourceType,
+    },
+    video_source::VideoSource,
     video_source_gst::{VideoSourceGst, VideoSourceGstType},
     video_source_local::VideoSourceLocal,
 };
 use crate::video_stream::types::VideoAndStreamInformation;
 use log::*;
 use simple_error::SimpleError;
+use std::net::{IpAddr, ToSocketAddrs};
+use url::{Host, Url};
 
 pub trait StreamBackend {
     fn start(&mut self) -> bool;
@@ -16,15 +24,73 @@ pub trait StreamBackend {
     fn restart(&mut self);
     fn set_pipeline_description(&mut self, description: &str);
     fn pipeline(&self) -> String;
+    fn set_resolution(&mut self, resolution: Resolution);
+    fn resolution(&self) -> Option<Resolution>;
+    fn negotiated_caps(&self) -> Option<NegotiatedCaps>;
+    fn pipeline_graph(&self) -> Option<String>;
+    fn pipeline_state(&self) -> Option<gstreamer::State>;
+    fn bytes_sent(&self) -> Option<u64>;
+    fn queue_overruns(&self) -> u64;
+    fn source_disconnected(&self) -> bool;
+    fn consecutive_failures(&self) -> u32;
+    fn last_error(&self) -> Option<String>;
+    fn flush(&self) -> Option<bool>;
+    // Called each time the pipeline reaches the playing state
+    fn set_playing_callback(&mut self, callback: Box<dyn Fn() + Send + Sync>);
+    // Called each time the pipeline starts, stops, fails or reaches the playing state
+    fn set_state_callback(&mut self, callback: Box<dyn Fn() + Send + Sync>);
+    // Block until the pipeline reaches the playing state, false if it did not before the timeout
+    fn wait_playing(&self, timeout: std::time::Duration) -> bool;
 }
 
 pub fn new(
     video_and_stream_information: &VideoAndStreamInformation,
 ) -> Result<StreamType, SimpleError> {
+    check(video_and_stream_information)?;
+    return create_stream(video_and_stream_information);
+}
+
+// Limits of the multiudpsink socket send buffer size, in bytes
+const MIN_UDP_BUFFER_SIZE: u32 = 1024;
+const MAX_UDP_BUFFER_SIZE: u32 = 64 * 1024 * 1024;
+
+const MIN_UDP_PORT: u16 = 1024;
+
+// SPS and PPS are sent every 10 seconds by default, rtph264pay accepts up to one hour
+const DEFAULT_CONFIG_INTERVAL: i32 = 10;
+const MAX_CONFIG_INTERVAL: i32 = 3600;
+
+// x264 does not accept more than 16 consecutive B-frames
+const MAX_B_FRAMES: u32 = 16;
+
+// Limits of the RTP packets size, in bytes
+const MIN_RTP_MTU: u32 = 200;
+const MAX_RTP_MTU: u32 = 9000;
+
+// SRT only accepts passphrases in this length range
+const MIN_SRT_PASSPHRASE_LENGTH: usize = 10;
+const MAX_SRT_PASSPHRASE_LENGTH: usize = 79;
+
+// Dynamic RTP payload types
+const DEFAULT_PAYLOAD_TYPE: u8 = 96;
+const MIN_PAYLOAD_TYPE: u8 = 96;
+const MAX_PAYLOAD_TYPE: u8 = 127;
+
+// Video RTP streams use a 90 kHz clock (RFC 3551)
+const RTP_VIDEO_CLOCK_RATE: u32 = 90000;
+
+// Multicast packets do not leave the local network by default
+const DEFAULT_MULTICAST_TTL: u8 = 1;
+
+// Name of the multiudpsink that sends the RTCP sender reports, used to change its clients
+pub const RTCP_SINK_NAME: &str = "rtcp_sink";
+
+// Validate the stream information without creating a stream
+pub fn check(video_and_stream_information: &VideoAndStreamInformation) -> Result<(), SimpleError> {
     check_endpoints(video_and_stream_information)?;
     check_encode(video_and_stream_information)?;
     check_scheme(video_and_stream_information)?;
-    return create_stream(video_and_stream_information);
+    return Ok(());
 }
 
 fn check_endpoints(
@@ -46,6 +112,24 @@ fn check_endpoints(
         )));
     }
 
+    check_duplicated_endpoints(endpoints)?;
+
+    return Ok(());
+}
+
+// Duplicated endpoints would receive the same packets twice
+fn check_duplicated_endpoints(endpoints: &[Url]) -> Result<(), SimpleError> {
+    for (index, endpoint) in endpoints.iter().enumerate() {
+        if endpoints[..index]
+            .iter()
+            .any(|other| same_destination(endpoint, other))
+        {
+            return Err(SimpleError::new(format!(
+                "Endpoint is duplicated: {}",
+                endpoint
+            )));
+        }
+    }
     return Ok(());
 }
 
@@ -72,6 +156,57 @@ fn check_encode(
         )));
     }
 
+    let frame_interval = &video_and_stream_information
+        .stream_information
+        .configuration
+        .frame_interval;
+    FrameInterval::new(frame_interval.numerator, frame_interval.denominator)?;
+
+    if let Some(config_interval) = video_and_stream_information
+        .stream_information
+        .config_interval
+    {
+        if !(-1..=MAX_CONFIG_INTERVAL).contains(&config_interval) {
+            return Err(SimpleError::new(format!(
+                "SPS/PPS interval should be -1 (every keyframe), 0 (disabled) or up to {} seconds, used: {}",
+                MAX_CONFIG_INTERVAL, config_interval
+            )));
+        }
+    }
+
+    if let Some(0) = video_and_stream_information
+        .stream_information
+        .configuration
+        .keyframe_interval
+    {
+        return Err(SimpleError::new(
+            "Keyframe interval should be bigger than zero".to_string(),
+        ));
+    }
+
+    if let Some(b_frames) = video_and_stream_information
+        .stream_information
+        .configuration
+        .b_frames
+    {
+        if b_frames > MAX_B_FRAMES {
+            return Err(SimpleError::new(format!(
+                "B-frames should be between 0 and {}, used: {}",
+                MAX_B_FRAMES, b_frames
+            )));
+        }
+    }
+
+    if let Some(0) = video_and_stream_information
+        .stream_information
+        .configuration
+        .encoder_threads
+    {
+        return Err(SimpleError::new(
+            "Encoder threads should be bigger than zero".to_string(),
+        ));
+    }
+
     return Ok(());
 }
 
@@ -115,11 +250,139 @@ fn check_scheme(
                     endpoints
                 )));
             }
+
+            check_udp_ports(endpoints)?;
+            udp_clients(endpoints)?;
+
+            if let Some(buffer_size) = video_and_stream_information
+                .stream_information
+                .udp_buffer_size
+            {
+                if !(MIN_UDP_BUFFER_SIZE..=MAX_UDP_BUFFER_SIZE).contains(&buffer_size) {
+                    return Err(SimpleError::new(format!(
+                        "UDP buffer size should be between {} and {} bytes, used: {}",
+                        MIN_UDP_BUFFER_SIZE, MAX_UDP_BUFFER_SIZE, buffer_size
+                    )));
+                }
+            }
+
+            if let Some(rtp_mtu) = video_and_stream_information.stream_information.rtp_mtu {
+                if !(MIN_RTP_MTU..=MAX_RTP_MTU).contains(&rtp_mtu) {
+                    return Err(SimpleError::new(format!(
+                        "RTP MTU should be between {} and {} bytes, used: {}",
+                        MIN_RTP_MTU, MAX_RTP_MTU, rtp_mtu
+                    )));
+                }
+            }
+
+            // RTCP uses the port after the RTP port
+            if video_and_stream_information.stream_information.rtcp
+                && endpoints
+                    .iter()
+                    .any(|endpoint| endpoint.port() == Some(65535))
+            {
+                return Err(SimpleError::new(format!(
+                    "RTCP is sent to the port after the RTP port, the RTP port should be lower than 65535. Endpoints: {:#?}",
+                    endpoints
+                )));
+            }
+
+            let options = udp_endpoint_options(endpoints)?;
+            if let Some(payload_type) = options.payload_type {
+                if !(MIN_PAYLOAD_TYPE..=MAX_PAYLOAD_TYPE).contains(&payload_type) {
+                    return Err(SimpleError::new(format!(
+                        "RTP payload type should be between {} and {}, used: {}",
+                        MIN_PAYLOAD_TYPE, MAX_PAYLOAD_TYPE, payload_type
+                    )));
+                }
+            }
+            if multicast_ttl(&video_and_stream_information.stream_information)? == 0 {
+                return Err(SimpleError::new(
+                    "Multicast TTL should be bigger than zero".to_string(),
+                ));
+            }
+            let unicast_ttl = endpoints.iter().any(|endpoint| {
+                !is_multicast(endpoint) && endpoint.query_pairs().any(|(key, _)| key == "ttl")
+            });
+            if unicast_ttl {
+                return Err(SimpleError::new(format!(
+                    "The ttl option is only accepted by multicast endpoints (224.0.0.0/4 or ff00::/8). Endpoints: {:#?}",
+                    endpoints
+                )));
+            }
+
+            // The local network control block is reserved for routing protocols
+            let reserved_multicast =
+                endpoints
+                    .iter()
+                    .any(|endpoint| match endpoint_address(endpoint) {
+                        Some(IpAddr::V4(address)) => address.octets()[..3] == [224, 0, 0],
+                        _ => false,
+                    });
+            if reserved_multicast {
+                return Err(SimpleError::new(format!(
+                    "Multicast addresses in 224.0.0.0/24 are reserved, use another multicast group. Endpoints: {:#?}",
+                    endpoints
+                )));
+            }
+
+            let multicast_endpoint = endpoints.iter().any(is_multicast);
+            if video_and_stream_information
+                .stream_information
+                .multicast_ttl
+                .is_some()
+                && !multicast_endpoint
+            {
+                return Err(SimpleError::new(format!(
+                    "Multicast TTL requires a multicast endpoint (224.0.0.0/4 or ff00::/8). Endpoints: {:#?}",
+                    endpoints
+                )));
+            }
         }
         "udp265" => {
             if VideoEncodeType::H265 != encode {
                 return Err(SimpleError::new(format!("Endpoint with udp265 scheme only supports H265 encode. Encode: {:?}, Endpoints: {:#?}", encode, endpoints)));
             }
+
+            check_udp_ports(endpoints)?;
+        }
+        "file" => {
+            if VideoEncodeType::H264 != encode {
+                return Err(SimpleError::new(format!("Endpoint with file scheme only supports H264 encode. Encode: {:?}, Endpoints: {:#?}", encode, endpoints)));
+            }
+
+            if endpoints.len() > 1 {
+                return Err(SimpleError::new(format!(
+                    "Multiple file endpoints are not acceptable: {:#?}",
+                    endpoints
+                )));
+            }
+
+            check_file_path(endpoints.first().unwrap())?;
+
+            if let Some(0) = video_and_stream_information
+                .stream_information
+                .segment_duration
+            {
+                return Err(SimpleError::new(
+                    "Segment duration should be bigger than zero".to_string(),
+                ));
+            }
+        }
+        "srt" => {
+            if VideoEncodeType::H264 != encode {
+                return Err(SimpleError::new(format!("Endpoint with srt scheme only supports H264 encode. Encode: {:?}, Endpoints: {:#?}", encode, endpoints)));
+            }
+
+            if endpoints.len() > 1 {
+                return Err(SimpleError::new(format!(
+                    "Multiple SRT endpoints are not acceptable: {:#?}",
+                    endpoints
+                )));
+            }
+
+            srt_sink(endpoints.first().unwrap())?;
+            check_srt_elements()?;
         }
         _ => {
             return Err(SimpleError::new(format!(
@@ -132,128 +395,1170 @@ fn check_scheme(
     return Ok(());
 }
 
-fn create_udp_stream(
+// Element that draws the overlay on raw video, clockoverlay shows its text before the time
+fn overlay_description(overlay: &TextOverlay, stream_name: &str) -> Result<String, SimpleError> {
+    if overlay.text.is_empty() && !overlay.show_timestamp {
+        return Err(SimpleError::new(
+            "Overlay should have a text or show the timestamp".to_string(),
+        ));
+    }
+
+    let text = overlay
+        .text
+        .replace("{name}", stream_name)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    let (valignment, halignment) = match overlay.position {
+        OverlayPosition::TopLeft => ("top", "left"),
+        OverlayPosition::TopRight => ("top", "right"),
+        OverlayPosition::BottomLeft => ("bottom", "left"),
+        OverlayPosition::BottomRight => ("bottom", "right"),
+    };
+
+    let element = if overlay.show_timestamp {
+        "clockoverlay time-format=\"%Y-%m-%d %H:%M:%S\""
+    } else {
+        "textoverlay"
+    };
+    return Ok(format!(
+        "{} text=\"{}\" valignment={} halignment={}",
+        element, text, valignment, halignment
+    ));
+}
+
+// Element that removes the borders of raw video, at least one pixel should be left in each direction
+fn crop_description(crop: &Crop, resolution: &Resolution) -> Result<String, SimpleError> {
+    if crop.left as u64 + crop.right as u64 >= resolution.width as u64
+        || crop.top as u64 + crop.bottom as u64 >= resolution.height as u64
+    {
+        return Err(SimpleError::new(format!(
+            "Crop should be inside the captured resolution {}x{}, used: {:?}",
+            resolution.width, resolution.height, crop
+        )));
+    }
+
+    return Ok(format!(
+        "videocrop left={} right={} top={} bottom={}",
+        crop.left, crop.right, crop.top, crop.bottom
+    ));
+}
+
+// Names of the elements of a pipeline description, caps filters are skipped
+fn pipeline_elements(pipeline: &str) -> Vec<&str> {
+    let mut elements: Vec<&str> = vec![];
+    // Pads of named elements, like rtpbin.send_rtp_src_0, are not elements
+    for element in pipeline
+        .split(" ! ")
+        .filter_map(|segment| {
+            segment
+                .split_whitespace()
+                .find(|token| !token.contains('.') || token.contains('='))
+        })
+        .filter(|name| !name.contains('/') && !name.contains('='))
+    {
+        if !elements.contains(&element) {
+            elements.push(element);
+        }
+    }
+    return elements;
+}
+
+// Check that the elements of the pipeline are installed, so the stream fails with a clear error
+// instead of a parse error of the pipeline when it starts
+pub fn check_installed_elements(
     video_and_stream_information: &VideoAndStreamInformation,
-) -> Result<StreamType, SimpleError> {
-    let encode = video_and_stream_information
-        .stream_information
-        .configuration
-        .encode
-        .clone();
-    let endpoints = &video_and_stream_information.stream_information.endpoints;
-    let configuration = &video_and_stream_information
-        .stream_information
-        .configuration;
-    let video_source = &video_and_stream_information.video_source;
+    pipeline: &str,
+) -> Result<(), SimpleError> {
+    let elements = utils::elements_information(&pipeline_elements(pipeline))?;
+    return check_missing_elements(video_and_stream_information, pipeline, |name| {
+        elements[name].present
+    });
+}
 
-    let video_format = match video_source {
-        VideoSourceType::Local(local_device) => {
-            if VideoEncodeType::H264 == encode {
-                format!(
-                    concat!(
-                        "v4l2src device={device}",
-                        " ! video/x-h264,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
-                    ),
-                    device = &local_device.device_path,
-                    width = configuration.width,
-                    height = configuration.height,
-                    interval_denominator = configuration.frame_interval.denominator,
-                    interval_numerator = configuration.frame_interval.numerator,
-                )
-            } else {
-                return Err(SimpleError::new(format!(
-                    "Unsupported encode for UDP endpoint: {:?}",
-                    encode
-                )));
-            }
+fn check_missing_elements<F>(
+    video_and_stream_information: &VideoAndStreamInformation,
+    pipeline: &str,
+    is_installed: F,
+) -> Result<(), SimpleError>
+where
+    F: Fn(&str) -> bool,
+{
+    let missing: Vec<String> = pipeline_elements(pipeline)
+        .into_iter()
+        .filter(|name| !is_installed(name))
+        .map(|name| format!("'{}'", name))
+        .collect();
+
+    let names = match missing.split_last() {
+        None => return Ok(()),
+        Some((last, [])) => format!("the {} element, which is", last),
+        Some((last, others)) => {
+            format!("the {} and {} elements, which are", others.join(", "), last)
         }
-        VideoSourceType::Gst(gst_source) => match &gst_source.source {
-            VideoSourceGstType::Fake(pattern) => {
-                format!(
-                        concat!(
-                            "videotestsrc pattern={pattern}",
-                            " ! video/x-raw,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
-                            " ! videoconvert",
-                            " !  x264enc bitrate=5000",
-                            " ! video/x-h264, profile=baseline",
-                        ),
-                        pattern = pattern,
-                        width = configuration.width,
-                        height = configuration.height,
-                        interval_denominator = configuration.frame_interval.denominator,
-                        interval_numerator = configuration.frame_interval.numerator,
-                    )
+    };
+
+    let stream_information = &video_and_stream_information.stream_information;
+    return Err(SimpleError::new(format!(
+        "{:?} {} streaming requires {} not installed",
+        stream_information.configuration.encode,
+        stream_information
+            .endpoints
+            .first()
+            .unwrap()
+            .scheme()
+            .to_uppercase(),
+        names
+    )));
+}
+
+// Recordings are written as Matroska files in an existing directory
+fn check_file_path(endpoint: &Url) -> Result<(), SimpleError> {
+    let path = match endpoint.to_file_path() {
+        Ok(path) => path,
+        Err(_) => {
+            return Err(SimpleError::new(format!(
+                "Endpoint with file scheme should contain an absolute path: {}",
+                endpoint
+            )));
+        }
+    };
+
+    if path
+        .extension()
+        .map_or(true, |extension| extension != "mkv")
+    {
+        return Err(SimpleError::new(format!(
+            "Recording file should have the mkv extension: {}",
+            path.display()
+        )));
+    }
+
+    match path.parent() {
+        Some(directory) if directory.is_dir() => {}
+        _ => {
+            return Err(SimpleError::new(format!(
+                "Recording directory does not exist: {}",
+                path.display()
+            )));
+        }
+    }
+
+    return Ok(());
+}
+
+// The SRT plugin is part of gst-plugins-bad and it's not always installed
+fn check_srt_elements() -> Result<(), SimpleError> {
+    let elements = utils::elements_information(&["mpegtsmux", "srtsink"])?;
+    let missing: Vec<&String> = elements
+        .iter()
+        .filter(|(_, information)| !information.present)
+        .map(|(name, _)| name)
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(SimpleError::new(format!(
+            "SRT endpoints require the GStreamer elements {:?}, install gst-plugins-bad with the srt plugin",
+            missing
+        )));
+    }
+
+    return Ok(());
+}
+
+// Convert the endpoint to the srtsink description,
+// the SRT options are taken from the query: srt://host:port?latency=125&passphrase=secret&mode=listener
+fn srt_sink(endpoint: &Url) -> Result<String, SimpleError> {
+    let (host, port) = match (endpoint.host_str(), endpoint.port()) {
+        (Some(host), Some(port)) if !host.is_empty() => (host, port),
+        _ => {
+            return Err(SimpleError::new(format!(
+                "Endpoint with srt scheme should contain host and port: {}",
+                endpoint
+            )))
+        }
+    };
+
+    let mut sink = format!("srtsink uri=srt://{}:{}", host, port);
+    for (key, value) in endpoint.query_pairs() {
+        match key.as_ref() {
+            "latency" => {
+                let latency: u32 = value.parse().map_err(|_| {
+                    SimpleError::new(format!(
+                        "SRT latency should be a number of milliseconds, used: {}",
+                        value
+                    ))
+                })?;
+                sink.push_str(&format!(" latency={}", latency));
             }
+            "passphrase" => {
+                let length = value.chars().count();
+                if !(MIN_SRT_PASSPHRASE_LENGTH..=MAX_SRT_PASSPHRASE_LENGTH).contains(&length) {
+                    return Err(SimpleError::new(format!(
+                        "SRT passphrase should have between {} and {} characters, used: {}",
+                        MIN_SRT_PASSPHRASE_LENGTH, MAX_SRT_PASSPHRASE_LENGTH, length
+                    )));
+                }
+                if value.contains(|character: char| character == '"' || character == '\\') {
+                    return Err(SimpleError::new(
+                        "SRT passphrase should not contain quotes or backslashes".to_string(),
+                    ));
+                }
+                sink.push_str(&format!(" passphrase=\"{}\"", value));
+            }
+            "mode" => match value.as_ref() {
+                "caller" | "listener" => sink.push_str(&format!(" mode={}", value)),
+                _ => {
+                    return Err(SimpleError::new(format!(
+                        "SRT mode should be caller or listener, used: {}",
+                        value
+                    )))
+                }
+            },
             _ => {
                 return Err(SimpleError::new(format!(
-                    "Unsupported GST source for UDP endpoint: {:#?}",
-                    gst_source
-                )));
+                    "Unknown SRT option {}, accepted options are latency, passphrase and mode: {}",
+                    key, endpoint
+                )))
             }
-        },
+        }
+    }
+
+    return Ok(sink);
+}
+
+// The SRT passphrase is a secret, it should not be shown in logs or in the streams status
+const REDACTED_PASSPHRASE: &str = "*****";
+
+pub fn redacted_endpoint(endpoint: &Url) -> Url {
+    if !endpoint.query_pairs().any(|(key, _)| key == "passphrase") {
+        return endpoint.clone();
+    }
+
+    let pairs: Vec<(String, String)> = endpoint
+        .query_pairs()
+        .map(|(key, value)| match key.as_ref() {
+            "passphrase" => (key.to_string(), REDACTED_PASSPHRASE.to_string()),
+            _ => (key.to_string(), value.to_string()),
+        })
+        .collect();
+
+    let mut endpoint = endpoint.clone();
+    endpoint.query_pairs_mut().clear().extend_pairs(pairs);
+    return endpoint;
+}
+
+// The passphrase of srtsink is quoted and can't contain quotes, as checked by srt_sink
+fn redacted_pipeline(pipeline: &str) -> String {
+    let key = "passphrase=\"";
+    let start = match pipeline.find(key) {
+        Some(position) => position + key.len(),
+        None => return pipeline.to_string(),
+    };
+    let end = match pipeline[start..].find('"') {
+        Some(position) => start + position,
+        None => return pipeline.to_string(),
     };
 
-    if VideoEncodeType::H264 == encode {
-        let udp_encode = concat!(
-            " ! h264parse",
-            " ! queue",
-            " ! rtph264pay config-interval=10 pt=96",
-        );
+    return [&pipeline[..start], REDACTED_PASSPHRASE, &pipeline[end..]].join("");
+}
 
-        let clients: Vec<String> = endpoints
-            .iter()
-            .map(|endpoint| format!("{}:{}", endpoint.host().unwrap(), endpoint.port().unwrap()))
-            .collect();
-        let clients = clients.join(",");
+// Options taken from the query of UDP endpoints, E.g: udp://239.0.0.1:5600?pt=97&ttl=2
+#[derive(Debug, Default, PartialEq)]
+struct UdpEndpointOptions {
+    payload_type: Option<u8>,
+    multicast_ttl: Option<u8>,
+}
 
-        let udp_sink = format!(" ! multiudpsink clients={}", clients);
+// All endpoints share the payloader and the sink, the options can't be different between them.
+// Endpoints without an option use the one of the other endpoints or the stream default
+fn udp_endpoint_options(endpoints: &[Url]) -> Result<UdpEndpointOptions, SimpleError> {
+    let merge = |option: &mut Option<u8>, name: &str, value: &str, endpoint: &Url| {
+        let value: u8 = value.parse().map_err(|_| {
+            SimpleError::new(format!(
+                "Option {} should be a number between 0 and 255, used: {} ({})",
+                name, value, endpoint
+            ))
+        })?;
+        match option {
+            Some(other) if *other != value => Err(SimpleError::new(format!(
+                "Option {} should have the same value in all endpoints, used: {} and {}",
+                name, other, value
+            ))),
+            _ => {
+                *option = Some(value);
+                Ok(())
+            }
+        }
+    };
 
-        let pipeline = [&video_format, udp_encode, &udp_sink].join("");
-        info!("Created pipeline: {}", pipeline);
-        let mut stream = VideoStreamUdp::default();
-        stream.set_pipeline_description(&pipeline);
-        return Ok(StreamType::UDP(stream));
+    let mut options = UdpEndpointOptions::default();
+    for endpoint in endpoints {
+        for (key, value) in endpoint.query_pairs() {
+            match key.as_ref() {
+                "pt" => merge(&mut options.payload_type, "pt", &value, endpoint)?,
+                "ttl" => merge(&mut options.multicast_ttl, "ttl", &value, endpoint)?,
+                _ => warn!("Ignoring unknown option {} of endpoint {}", key, endpoint),
+            }
+        }
     }
 
-    return Err(SimpleError::new(format!(
-        "Unsupported encode: {:?}",
-        encode
-    )));
+    return Ok(options);
 }
 
-fn create_stream(
+// TTL of the multicast packets, the ttl option of the endpoints is used over the one of the stream
+fn multicast_ttl(stream_information: &StreamInformation) -> Result<u8, SimpleError> {
+    let options = udp_endpoint_options(&stream_information.endpoints)?;
+    return Ok(options
+        .multicast_ttl
+        .or(stream_information.multicast_ttl)
+        .unwrap_or(DEFAULT_MULTICAST_TTL));
+}
+
+// Parameters of the RTP packets sent by UDP streams, None for the other streams
+pub fn rtp_parameters(
     video_and_stream_information: &VideoAndStreamInformation,
-) -> Result<StreamType, SimpleError> {
-    // The scheme was validated by "new" function
-    let endpoint = &video_and_stream_information
-        .stream_information
-        .endpoints
-        .iter()
-        .next()
-        .unwrap();
-    match endpoint.scheme() {
-        "udp" => create_udp_stream(video_and_stream_information),
-        something => Err(SimpleError::new(format!(
-            "Unsupported scheme: {}",
-            something
-        ))),
+) -> Result<Option<RtpParameters>, SimpleError> {
+    let stream_information = &video_and_stream_information.stream_information;
+    match stream_information.endpoints.first() {
+        Some(endpoint) if endpoint.scheme() == "udp" => {}
+        _ => return Ok(None),
     }
+
+    let encoding_name = match stream_information.configuration.encode {
+        VideoEncodeType::H264 => "H264",
+        _ => return Ok(None),
+    };
+
+    let options = udp_endpoint_options(&stream_information.endpoints)?;
+    return Ok(Some(RtpParameters {
+        media: "video".into(),
+        encoding_name: encoding_name.into(),
+        clock_rate: RTP_VIDEO_CLOCK_RATE,
+        payload_type: options.payload_type.unwrap_or(DEFAULT_PAYLOAD_TYPE),
+    }));
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::video::{
-        types::{CaptureConfiguration, FrameInterval},
-        video_source_local::{VideoSourceLocal, VideoSourceLocalType},
+// SDP description of UDP streams for the first endpoint, used by players to receive the RTP packets.
+// None for the streams that are not sent over RTP
+pub fn sdp(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<Option<String>, SimpleError> {
+    let rtp = match rtp_parameters(video_and_stream_information)? {
+        Some(rtp) => rtp,
+        None => return Ok(None),
     };
+    let stream_information = &video_and_stream_information.stream_information;
+    let endpoint = match stream_information.endpoints.first() {
+        Some(endpoint) => endpoint,
+        None => return Ok(None),
+    };
+    let (address, port) = udp_client_address(endpoint)?;
 
-    use url::Url;
+    let address_type = match address.contains(':') {
+        true => "IP6",
+        false => "IP4",
+    };
+    // IPv4 multicast addresses carry the time to live of the packets
+    let connection_address = match endpoint_address(endpoint) {
+        Some(IpAddr::V4(ip)) if ip.is_multicast() => {
+            format!("{}/{}", address, multicast_ttl(stream_information)?)
+        }
+        _ => address,
+    };
 
-    #[test]
+    let lines = [
+        "v=0".to_string(),
+        "o=- 0 0 IN IP4 127.0.0.1".to_string(),
+        format!("s={}", video_and_stream_information.name),
+        format!("c=IN {} {}", address_type, connection_address),
+        "t=0 0".to_string(),
+        format!("m={} {} RTP/AVP {}", rtp.media, port, rtp.payload_type),
+        format!(
+            "a=rtpmap:{} {}/{}",
+            rtp.payload_type, rtp.encoding_name, rtp.clock_rate
+        ),
+        // rtph264pay uses the non-interleaved mode, with FU-A and STAP-A packets
+        format!("a=fmtp:{} packetization-mode=1", rtp.payload_type),
+    ];
+    return Ok(Some(lines.join("\r\n") + "\r\n"));
+}
+
+// Ports below 1024 are reserved for system services and can't be used by the receivers
+fn check_udp_ports(endpoints: &[Url]) -> Result<(), SimpleError> {
+    for endpoint in endpoints {
+        if let Some(port) = endpoint.port() {
+            if port < MIN_UDP_PORT {
+                return Err(SimpleError::new(format!(
+                    "UDP port should be between {} and 65535, used: {} ({})",
+                    MIN_UDP_PORT, port, endpoint
+                )));
+            }
+        }
+    }
+    return Ok(());
+}
+
+// Return the frame interval that the source should be configured with when it
+// can't provide the configured one, the extra frames are dropped by videorate.
+// None means that the source already provides the configured frame interval.
+fn source_frame_interval(
+    formats: &[Format],
+    configuration: &CaptureConfiguration,
+) -> Option<FrameInterval> {
+    let requested = &configuration.frame_interval;
+    let intervals: Vec<FrameInterval> = formats
+        .iter()
+        .flat_map(|format| format.sizes.clone())
+        .filter(|size| size.width == configuration.width && size.height == configuration.height)
+        .flat_map(|size| size.intervals)
+        .filter(|interval| interval.numerator != 0 && interval.denominator != 0)
+        .collect();
+
+    if intervals.is_empty() || intervals.contains(requested) {
+        return None;
+    }
+
+    // Frames per second are denominator / numerator
+    let compare_fps = |first: &FrameInterval, second: &FrameInterval| {
+        (first.denominator as u64 * second.numerator as u64)
+            .cmp(&(second.denominator as u64 * first.numerator as u64))
+    };
+
+    // videorate with drop-only can only reduce the framerate,
+    // use the slowest source frame interval that is still faster than the requested one
+    return intervals
+        .into_iter()
+        .filter(|interval| compare_fps(interval, requested) == std::cmp::Ordering::Greater)
+        .min_by(compare_fps);
+}
+
+// IP address of the endpoint host, udp is not a special scheme for the url crate
+// so IPv4 addresses are parsed as domains
+fn endpoint_address(endpoint: &Url) -> Option<IpAddr> {
+    return match endpoint.host()? {
+        Host::Ipv4(address) => Some(IpAddr::V4(address)),
+        Host::Ipv6(address) => Some(IpAddr::V6(address)),
+        Host::Domain(domain) => domain.parse().ok(),
+    };
+}
+
+fn is_multicast(endpoint: &Url) -> bool {
+    return endpoint_address(endpoint).map_or(false, |address| address.is_multicast());
+}
+
+// Where the stream is sent to, the options in the query of the endpoint are not part of it
+pub fn endpoint_destination(endpoint: &Url) -> String {
+    return match (endpoint.host(), endpoint.port()) {
+        (Some(host), Some(port)) => format!("{}://{}:{}", endpoint.scheme(), host, port),
+        // Files don't have a host or port, only the path matters
+        _ => {
+            let mut destination = endpoint.clone();
+            destination.set_query(None);
+            destination.set_fragment(None);
+            destination.to_string()
+        }
+    };
+}
+
+// Endpoints that send the stream to the same place
+pub fn same_destination(endpoint: &Url, other: &Url) -> bool {
+    return endpoint_destination(endpoint) == endpoint_destination(other);
+}
+
+// Convert the endpoint to the host:port format used by multiudpsink, DNS names are resolved
+// since it only deals with IP addresses. It splits each client on the last colon to find the port,
+// so IPv6 addresses are used without the URL brackets
+fn udp_client(endpoint: &Url) -> Result<String, SimpleError> {
+    let (address, port) = udp_client_address(endpoint)?;
+    return Ok(format!("{}:{}", address, port));
+}
+
+// Address and port of the endpoint as used by multiudpsink, host names are resolved
+fn udp_client_address(endpoint: &Url) -> Result<(String, u16), SimpleError> {
+    let (host, port) = match (endpoint.host(), endpoint.port()) {
+        (Some(host), Some(port)) => (host, port),
+        _ => {
+            return Err(SimpleError::new(format!(
+                "Endpoint should contain host and port: {}",
+                endpoint
+            )))
+        }
+    };
+
+    let address = match host {
+        Host::Ipv4(address) => address.to_string(),
+        Host::Ipv6(address) => address.to_string(),
+        Host::Domain(domain) => match (domain, port).to_socket_addrs() {
+            Ok(mut addresses) => match addresses.next() {
+                Some(address) => address.ip().to_string(),
+                None => {
+                    return Err(SimpleError::new(format!(
+                        "Host {} of endpoint {} has no address",
+                        domain, endpoint
+                    )))
+                }
+            },
+            Err(error) => {
+                return Err(SimpleError::new(format!(
+                    "Failed to resolve host {} of endpoint {}, use an IP address instead: {}",
+                    domain, endpoint, error
+                )))
+            }
+        },
+    };
+
+    return Ok((address, port));
+}
+
+// Select the H264 encoder for raw sources, hardware encoders are preferred
+pub fn select_h264_encoder(configuration: &CaptureConfiguration) -> Result<String, SimpleError> {
+    let encoder = if cli::manager::is_software_encoder() {
+        H264Encoder::X264
+    } else {
+        utils::available_h264_encoder()
+    };
+
+    let preferred_pixel_format = preferred_pixel_format(&encoder);
+    if configuration.pixel_format != preferred_pixel_format {
+        warn!(
+            "Encoder {:?} prefers {} pixel format, using {} may require an extra conversion.",
+            encoder, preferred_pixel_format, configuration.pixel_format
+        );
+    }
+
+    return h264_encoder_description(&encoder, configuration);
+}
+
+// Raw format that the encoder uses without any internal conversion
+fn preferred_pixel_format(encoder: &H264Encoder) -> PixelFormat {
+    return match encoder {
+        H264Encoder::X264 => PixelFormat::I420,
+        H264Encoder::V4l2 | H264Encoder::Omx | H264Encoder::NvV4l2 => PixelFormat::NV12,
+    };
+}
+
+// All encoders use a bitrate of 5000 kbps, followed by the caps of the encoded video.
+// The baseline profile has no B-frames, the main profile is used when they are requested
+fn h264_encoder_description(
+    encoder: &H264Encoder,
+    configuration: &CaptureConfiguration,
+) -> Result<String, SimpleError> {
+    let property = |name: &str, value: Option<u32>| match value {
+        Some(value) => format!("{}={}", name, value),
+        None => "".to_string(),
+    };
+    let keyframe_interval = configuration.keyframe_interval;
+    let b_frames = configuration.b_frames;
+    let uses_b_frames = b_frames.map_or(false, |b_frames| b_frames > 0);
+
+    if uses_b_frames && *encoder == H264Encoder::Omx {
+        return Err(SimpleError::new(format!(
+            "Encoder {:?} does not support B-frames, used: {}",
+            encoder,
+            b_frames.unwrap()
+        )));
+    }
+
+    let description = match encoder {
+        H264Encoder::X264 => format!(
+            "x264enc bitrate=5000 threads={}{}{}",
+            configuration
+                .encoder_threads
+                .unwrap_or_else(cli::manager::encoder_threads),
+            property(" key-int-max", keyframe_interval),
+            property(" bframes", b_frames),
+        ),
+        H264Encoder::V4l2 => format!(
+            "v4l2h264enc extra-controls=\"controls,video_bitrate=5000000{}{}\"",
+            property(",h264_i_frame_period", keyframe_interval),
+            property(",video_b_frames", b_frames),
+        ),
+        H264Encoder::Omx => format!(
+            "omxh264enc target-bitrate=5000000 control-rate=variable{}",
+            property(" periodicity-idr", keyframe_interval),
+        ),
+        H264Encoder::NvV4l2 => format!(
+            "nvvidconv ! video/x-raw(memory:NVMM) ! nvv4l2h264enc bitrate=5000000{}{}",
+            property(" iframeinterval", keyframe_interval),
+            property(" num-B-Frames", b_frames),
+        ),
+    };
+    let profile = if uses_b_frames { "main" } else { "baseline" };
+    return Ok(format!(
+        "{} ! video/x-h264, profile={}",
+        description, profile
+    ));
+}
+
+// Configured options that are only used by the encoder
+fn encoder_options(configuration: &CaptureConfiguration) -> Vec<&'static str> {
+    let mut options = vec![];
+    if configuration.keyframe_interval.is_some() {
+        options.push("keyframe_interval");
+    }
+    if configuration.b_frames.is_some() {
+        options.push("b_frames");
+    }
+    if configuration.encoder_threads.is_some() {
+        options.push("encoder_threads");
+    }
+    if configuration.pixel_format != PixelFormat::default() {
+        options.push("pixel_format");
+    }
+    return options;
+}
+
+// Capture from a local device, the video is only transcoded if the device
+// does not provide the requested encode
+fn local_capture(
+    device: &str,
+    formats: &[Format],
+    configuration: &CaptureConfiguration,
+    filters: Option<&str>,
+) -> Result<String, SimpleError> {
+    let source_encodes: Vec<VideoEncodeType> =
+        formats.iter().map(|format| format.encode.clone()).collect();
+    let caps_with_interval = |interval: &FrameInterval| {
+        format!(
+            "width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+            width = configuration.width,
+            height = configuration.height,
+            interval_denominator = interval.denominator,
+            interval_numerator = interval.numerator,
+        )
+    };
+    let caps = caps_with_interval(&configuration.frame_interval);
+
+    let source = match configuration.io_mode {
+        Some(io_mode) => format!("v4l2src device={} io-mode={}", device, io_mode),
+        None => format!("v4l2src device={}", device),
+    };
+
+    // Devices that can't be inspected are expected to provide the requested encode
+    if source_encodes.is_empty() || source_encodes.contains(&configuration.encode) {
+        if filters.is_some() {
+            return Err(SimpleError::new(format!(
+                "Crop and overlay require encoding the video, but device {} already provides {:?} and it's streamed as it is",
+                device, configuration.encode
+            )));
+        }
+        let encoder_options = encoder_options(configuration);
+        if !encoder_options.is_empty() {
+            return Err(SimpleError::new(format!(
+                "{} require encoding the video, but device {} already provides {:?} and it's streamed as it is",
+                encoder_options.join(", "),
+                device,
+                configuration.encode
+            )));
+        }
+        return Ok(format!(
+            "{source} ! video/x-h264,{caps}",
+            source = source,
+            caps = caps,
+        ));
+    }
+
+    let source_encode = if source_encodes.contains(&VideoEncodeType::YUYV) {
+        VideoEncodeType::YUYV
+    } else if source_encodes.contains(&VideoEncodeType::MJPG) {
+        VideoEncodeType::MJPG
+    } else {
+        return Err(SimpleError::new(format!(
+            "Device {} does not provide a format that can be encoded to {:?}: {:?}",
+            device, configuration.encode, source_encodes
+        )));
+    };
+
+    // The device runs faster when it can't provide the configured frame interval,
+    // videorate drops the extra frames before the encoder
+    let source_formats: Vec<Format> = formats
+        .iter()
+        .filter(|format| format.encode == source_encode)
+        .cloned()
+        .collect();
+    let (source_caps, video_rate) = match source_frame_interval(&source_formats, configuration) {
+        Some(interval) => (
+            caps_with_interval(&interval),
+            format!(
+                " ! videorate drop-only=true ! video/x-raw,framerate={}/{}",
+                configuration.frame_interval.denominator, configuration.frame_interval.numerator,
+            ),
+        ),
+        None => (caps, "".to_string()),
+    };
+    let source_format = match source_encode {
+        VideoEncodeType::YUYV => format!("video/x-raw,format=YUY2,{}", source_caps),
+        _ => format!("image/jpeg,{} ! jpegdec", source_caps),
+    };
+
+    return Ok(format!(
+        concat!(
+            "{source}",
+            " ! {source_format}",
+            "{video_rate}",
+            "{filters}",
+            " ! videoconvert",
+            " ! video/x-raw,format={pixel_format}",
+            " ! {encoder}",
+        ),
+        source = source,
+        source_format = source_format,
+        video_rate = video_rate,
+        filters = filters.map_or("".to_string(), |filters| format!(" ! {}", filters)),
+        pixel_format = configuration.pixel_format,
+        encoder = select_h264_encoder(configuration)?,
+    ));
+}
+
+// Clients of the RTCP multiudpsink, RTCP uses the port after the RTP port of each endpoint
+pub fn rtcp_clients(endpoints: &[Url]) -> Result<String, SimpleError> {
+    let clients: Result<Vec<String>, SimpleError> = endpoints
+        .iter()
+        .map(|endpoint| {
+            let (address, port) = udp_client_address(endpoint)?;
+            Ok(format!("{}:{}", address, port + 1))
+        })
+        .collect();
+    return Ok(clients?.join(","));
+}
+
+// Comma separated list of clients for multiudpsink, all endpoints should be valid clients
+pub fn udp_clients(endpoints: &[Url]) -> Result<String, SimpleError> {
+    if endpoints.is_empty() {
+        return Err(SimpleError::new(
+            "No UDP client available for multiudpsink.",
+        ));
+    }
+
+    let clients: Result<Vec<String>, SimpleError> = endpoints.iter().map(udp_client).collect();
+    return Ok(clients?.join(","));
+}
+
+// Select the capture resolution from the sizes provided by the source, the closest one
+// is used when the requested resolution is not available and that is allowed by the configuration
+fn closest_resolution(
+    sizes: &[Size],
+    configuration: &CaptureConfiguration,
+) -> Result<Resolution, SimpleError> {
+    let requested = Resolution {
+        width: configuration.width,
+        height: configuration.height,
+    };
+
+    // Sources that can't be inspected are expected to provide the requested resolution
+    if sizes.is_empty()
+        || sizes
+            .iter()
+            .any(|size| size.width == requested.width && size.height == requested.height)
+    {
+        return Ok(requested);
+    }
+
+    let available: Vec<String> = sizes
+        .iter()
+        .map(|size| format!("{}x{}", size.width, size.height))
+        .collect();
+    if !configuration.allow_closest_resolution {
+        return Err(SimpleError::new(format!(
+            "Resolution {}x{} is not available, the available resolutions are: {}",
+            requested.width,
+            requested.height,
+            available.join(", ")
+        )));
+    }
+
+    // Use the resolution with the closest number of pixels, and the closest width on ties
+    let pixels = |width: u32, height: u32| width as i64 * height as i64;
+    let closest = sizes
+        .iter()
+        .min_by_key(|size| {
+            (
+                (pixels(size.width, size.height) - pixels(requested.width, requested.height)).abs(),
+                (size.width as i64 - requested.width as i64).abs(),
+            )
+        })
+        .unwrap();
+
+    warn!(
+        "Resolution {}x{} is not available, using the closest one: {}x{}",
+        requested.width, requested.height, closest.width, closest.height
+    );
+    return Ok(Resolution {
+        width: closest.width,
+        height: closest.height,
+    });
+}
+
+// Resolution used to capture the video of the stream
+pub fn capture_resolution(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<Resolution, SimpleError> {
+    let configuration = &video_and_stream_information
+        .stream_information
+        .configuration;
+    let sizes: Vec<Size> = match &video_and_stream_information.video_source {
+        VideoSourceType::Local(local_device) => local_device
+            .formats()
+            .into_iter()
+            .flat_map(|format| format.sizes)
+            .collect(),
+        // Gst sources can generate any resolution
+        VideoSourceType::Gst(_) => vec![],
+    };
+
+    return closest_resolution(&sizes, configuration);
+}
+
+// Capture and encode part of the pipeline, shared by all stream endpoints
+fn capture_pipeline(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<String, SimpleError> {
+    let encode = video_and_stream_information
+        .stream_information
+        .configuration
+        .encode
+        .clone();
+    let resolution = capture_resolution(video_and_stream_information)?;
+    let configuration = &CaptureConfiguration {
+        width: resolution.width,
+        height: resolution.height,
+        ..video_and_stream_information
+            .stream_information
+            .configuration
+            .clone()
+    };
+    let video_source = &video_and_stream_information.video_source;
+
+    // Raw video filters, the video is cropped before drawing the overlay
+    let mut filters = vec![];
+    if let Some(crop) = &video_and_stream_information.stream_information.crop {
+        filters.push(crop_description(crop, &resolution)?);
+    }
+    if let Some(overlay) = &video_and_stream_information.stream_information.overlay {
+        filters.push(overlay_description(
+            overlay,
+            &video_and_stream_information.name,
+        )?);
+    }
+    let filters = match filters.is_empty() {
+        true => None,
+        false => Some(filters.join(" ! ")),
+    };
+
+    let video_format = match video_source {
+        VideoSourceType::Local(local_device) => {
+            if VideoEncodeType::H264 != encode {
+                return Err(SimpleError::new(format!(
+                    "Unsupported encode for stream endpoint: {:?}",
+                    encode
+                )));
+            }
+
+            local_capture(
+                &local_device.device_path,
+                &local_device.formats(),
+                configuration,
+                filters.as_deref(),
+            )?
+        }
+        VideoSourceType::Gst(gst_source) => match &gst_source.source {
+            // videotestsrc generates any framerate, no frames need to be dropped
+            VideoSourceGstType::Fake(pattern) => {
+                format!(
+                        concat!(
+                            "videotestsrc pattern={pattern}",
+                            " ! video/x-raw,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                            "{filters}",
+                            " ! videoconvert",
+                            " ! video/x-raw,format={pixel_format}",
+                            " ! {encoder}",
+                        ),
+                        pattern = pattern,
+                        width = configuration.width,
+                        height = configuration.height,
+                        interval_denominator = configuration.frame_interval.denominator,
+                        interval_numerator = configuration.frame_interval.numerator,
+                        filters = filters.map_or("".to_string(), |filters| format!(" ! {}", filters)),
+                        pixel_format = configuration.pixel_format,
+                        encoder = select_h264_encoder(configuration)?,
+                    )
+            }
+            _ => {
+                return Err(SimpleError::new(format!(
+                    "Unsupported GST source for stream endpoint: {:#?}",
+                    gst_source
+                )));
+            }
+        },
+    };
+
+    return Ok(video_format);
+}
+
+pub fn create_udp_pipeline(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<String, SimpleError> {
+    let encode = video_and_stream_information
+        .stream_information
+        .configuration
+        .encode
+        .clone();
+    let endpoints = &video_and_stream_information.stream_information.endpoints;
+    let video_format = capture_pipeline(video_and_stream_information)?;
+
+    if VideoEncodeType::H264 == encode {
+        let stream_information = &video_and_stream_information.stream_information;
+        let options = udp_endpoint_options(endpoints)?;
+        let mut udp_encode = format!(
+            concat!(
+                " ! h264parse",
+                " ! queue",
+                " ! rtph264pay config-interval={config_interval} pt={payload_type}",
+            ),
+            config_interval = stream_information
+                .config_interval
+                .unwrap_or(DEFAULT_CONFIG_INTERVAL),
+            payload_type = options.payload_type.unwrap_or(DEFAULT_PAYLOAD_TYPE),
+        );
+        if let Some(ssrc) = stream_information.ssrc {
+            udp_encode.push_str(&format!(" ssrc={}", ssrc));
+        }
+        if let Some(seqnum_offset) = stream_information.seqnum_offset {
+            udp_encode.push_str(&format!(" seqnum-offset={}", seqnum_offset));
+        }
+        if let Some(timestamp_offset) = stream_information.timestamp_offset {
+            udp_encode.push_str(&format!(" timestamp-offset={}", timestamp_offset));
+        }
+        if let Some(rtp_mtu) = stream_information.rtp_mtu {
+            udp_encode.push_str(&format!(" mtu={}", rtp_mtu));
+        }
+
+        let multicast = match endpoints.iter().any(is_multicast) {
+            true => format!(
+                " auto-multicast=true ttl-mc={}",
+                multicast_ttl(stream_information)?
+            ),
+            false => "".to_string(),
+        };
+
+        // The RTP packets go through rtpbin to have its sender reports sent by another sink
+        if stream_information.rtcp {
+            udp_encode
+                .push_str(" ! rtpbin.send_rtp_sink_0 rtpbin name=rtpbin rtpbin.send_rtp_src_0");
+        }
+
+        let mut udp_sink = format!(" ! multiudpsink clients={}", udp_clients(endpoints)?);
+        if let Some(buffer_size) = stream_information.udp_buffer_size {
+            udp_sink.push_str(&format!(" buffer-size={}", buffer_size));
+        }
+        udp_sink.push_str(&multicast);
+
+        // The reports are sent periodically, they should not wait for the pipeline clock
+        if stream_information.rtcp {
+            udp_sink.push_str(&format!(
+                " rtpbin.send_rtcp_src_0 ! multiudpsink name={} clients={} sync=false async=false{}",
+                RTCP_SINK_NAME,
+                rtcp_clients(endpoints)?,
+                multicast
+            ));
+        }
+
+        let pipeline = [video_format, udp_encode, udp_sink].join("");
+        info!("Created pipeline: {}", pipeline);
+        return Ok(pipeline);
+    }
+
+    return Err(SimpleError::new(format!(
+        "Unsupported encode: {:?}",
+        encode
+    )));
+}
+
+// Runner of the pipeline, it waits for the device of local sources when they are unplugged
+fn pipeline_runner(
+    video_and_stream_information: &VideoAndStreamInformation,
+    pipeline: &str,
+) -> Result<VideoStreamPipeline, SimpleError> {
+    let mut stream = VideoStreamPipeline::default();
+    stream.set_pipeline_description(pipeline);
+    stream.set_resolution(capture_resolution(video_and_stream_information)?);
+    if let VideoSourceType::Local(local_device) = &video_and_stream_information.video_source {
+        stream.set_source_device(&local_device.device_path);
+    }
+    return Ok(stream);
+}
+
+fn create_udp_stream(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<StreamType, SimpleError> {
+    let pipeline = create_udp_pipeline(video_and_stream_information)?;
+    return Ok(StreamType::UDP(pipeline_runner(
+        video_and_stream_information,
+        &pipeline,
+    )?));
+}
+
+// Record the stream in a single file, or in multiple files when the segment duration is set.
+// The segments are numbered after the file name: /path/video.mkv turns into /path/video_00000.mkv
+pub fn create_file_pipeline(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<String, SimpleError> {
+    let video_format = capture_pipeline(video_and_stream_information)?;
+    // The path was validated by the check
+    let path = video_and_stream_information
+        .stream_information
+        .endpoints
+        .first()
+        .unwrap()
+        .to_file_path()
+        .unwrap();
+
+    let file_sink = match video_and_stream_information
+        .stream_information
+        .segment_duration
+    {
+        Some(segment_duration) => format!(
+            " ! splitmuxsink muxer=matroskamux location={location} max-size-time={max_size_time}",
+            location = path.with_extension("").display().to_string() + "_%05d.mkv",
+            max_size_time = segment_duration as u64 * 1_000_000_000,
+        ),
+        None => format!(" ! matroskamux ! filesink location={}", path.display()),
+    };
+
+    let pipeline = [video_format, " ! h264parse ! queue".to_string(), file_sink].join("");
+    info!("Created pipeline: {}", pipeline);
+    return Ok(pipeline);
+}
+
+fn create_file_stream(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<StreamType, SimpleError> {
+    let pipeline = create_file_pipeline(video_and_stream_information)?;
+    let mut stream = pipeline_runner(video_and_stream_information, &pipeline)?;
+    stream.set_drain_on_stop(true);
+    return Ok(StreamType::FILE(stream));
+}
+
+// Send the stream as MPEG-TS over SRT, the SPS and PPS are sent with every keyframe
+// so receivers can join at any time
+pub fn create_srt_pipeline(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<String, SimpleError> {
+    let video_format = capture_pipeline(video_and_stream_information)?;
+    // There is a single endpoint, validated by the check
+    let srt_sink = srt_sink(
+        video_and_stream_information
+            .stream_information
+            .endpoints
+            .first()
+            .unwrap(),
+    )?;
+
+    let pipeline = format!(
+        "{} ! h264parse config-interval=-1 ! queue ! mpegtsmux alignment=7 ! {}",
+        video_format, srt_sink
+    );
+    info!("Created pipeline: {}", redacted_pipeline(&pipeline));
+    return Ok(pipeline);
+}
+
+fn create_srt_stream(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<StreamType, SimpleError> {
+    let pipeline = create_srt_pipeline(video_and_stream_information)?;
+    return Ok(StreamType::SRT(pipeline_runner(
+        video_and_stream_information,
+        &pipeline,
+    )?));
+}
+
+fn create_stream(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<StreamType, SimpleError> {
+    // The scheme was validated by "new" function
+    let endpoint = &video_and_stream_information
+        .stream_information
+        .endpoints
+        .iter()
+        .next()
+        .unwrap();
+    match endpoint.scheme() {
+        "udp" => create_udp_stream(video_and_stream_information),
+        "file" => create_file_stream(video_and_stream_information),
+        "srt" => create_srt_stream(video_and_stream_information),
+        something => Err(SimpleError::new(format!(
+            "Unsupported scheme: {}",
+            something
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::{
+        types::{CaptureConfiguration, FrameInterval, IoMode},
+        video_source_local::{VideoSourceLocal, VideoSourceLocalType},
+    };
+
+    use url::Url;
+
+    // Local H264 stream shared by the tests, changed with the struct update syntax
+    fn test_stream(endpoints: &[&str]) -> VideoAndStreamInformation {
+        return VideoAndStreamInformation {
+            name: "Test".into(),
+            enabled: true,
+            priority: 0,
+            description: None,
+            controls: vec![],
+            stream_information: StreamInformation {
+                endpoints: endpoints
+                    .iter()
+                    .map(|endpoint| Url::parse(endpoint).unwrap())
+                    .collect(),
+                configuration: CaptureConfiguration {
+                    encode: VideoEncodeType::H264,
+                    height: 720,
+                    width: 1080,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                    keyframe_interval: None,
+                    encoder_threads: None,
+                    b_frames: None,
+                    pixel_format: PixelFormat::I420,
+                    allow_closest_resolution: false,
+                    io_mode: None,
+                },
+                udp_buffer_size: None,
+                multicast_ttl: None,
+                config_interval: None,
+                segment_duration: None,
+                ssrc: None,
+                seqnum_offset: None,
+                timestamp_offset: None,
+                rtp_mtu: None,
+                overlay: None,
+                crop: None,
+                rtcp: false,
+            },
+            video_source: VideoSourceType::Local(VideoSourceLocal {
+                name: "PotatoCam".into(),
+                device_path: "/dev/video42".into(),
+                typ: VideoSourceLocalType::Unknown("TestPotatoCam".into()),
+            }),
+        };
+    }
+
+    #[test]
     fn test_udp() {
         let result = create_stream(&VideoAndStreamInformation {
             name: "Test".into(),
+            enabled: true,
+            priority: 0,
+            description: None,
+            controls: vec![],
             stream_information: StreamInformation {
                 endpoints: vec![Url::parse("udp://192.168.0.1:42").unwrap()],
                 configuration: CaptureConfiguration {
@@ -264,7 +1569,24 @@ mod tests {
                         numerator: 1,
                         denominator: 30,
                     },
+                    keyframe_interval: None,
+                    encoder_threads: None,
+                    b_frames: None,
+                    pixel_format: PixelFormat::I420,
+                    allow_closest_resolution: false,
+                    io_mode: None,
                 },
+                udp_buffer_size: None,
+                multicast_ttl: None,
+                config_interval: None,
+                segment_duration: None,
+                ssrc: None,
+                seqnum_offset: None,
+                timestamp_offset: None,
+                rtp_mtu: None,
+                overlay: None,
+                crop: None,
+                rtcp: false,
             },
             video_source: VideoSourceType::Local(VideoSourceLocal {
                 name: "PotatoCam".into(),
@@ -276,7 +1598,956 @@ mod tests {
         assert!(result.is_ok());
         let result = result.unwrap();
 
-        let StreamType::UDP(video_stream_udp) = result;
-        assert_eq!(video_stream_udp.pipeline(), "v4l2src device=/dev/video42 ! video/x-h264,width=1080,height=720,framerate=30/1 ! h264parse ! queue ! rtph264pay config-interval=10 pt=96 ! multiudpsink clients=192.168.0.1:42");
+        assert_eq!(result.inner().pipeline(), "v4l2src device=/dev/video42 ! video/x-h264,width=1080,height=720,framerate=30/1 ! h264parse ! queue ! rtph264pay config-interval=10 pt=96 ! multiudpsink clients=192.168.0.1:42");
+    }
+
+    #[test]
+    fn test_udp_fake_framerate() {
+        let fake_stream = |frame_interval: FrameInterval| {
+            let base = test_stream(&["udp://192.168.0.1:42"]);
+            let result = create_stream(&VideoAndStreamInformation {
+                video_source: VideoSourceType::Gst(VideoSourceGst {
+                    name: "Fake".into(),
+                    source: VideoSourceGstType::Fake("ball".into()),
+                }),
+                stream_information: StreamInformation {
+                    configuration: CaptureConfiguration {
+                        height: 480,
+                        width: 640,
+                        frame_interval,
+                        ..base.stream_information.configuration
+                    },
+                    ..base.stream_information
+                },
+                ..base
+            });
+
+            assert!(result.is_ok());
+            result.unwrap().inner().pipeline()
+        };
+
+        // The fake source generates the configured framerate, no frames are dropped
+        for fps in &[25, 120] {
+            let pipeline = fake_stream(FrameInterval {
+                numerator: 1,
+                denominator: *fps,
+            });
+            assert!(pipeline.contains(&format!(
+                "videotestsrc pattern=ball ! video/x-raw,width=640,height=480,framerate={}/1 ! videoconvert",
+                fps
+            )));
+            assert!(!pipeline.contains("videorate"));
+        }
+    }
+
+    // Formats of a device that can't report its sizes
+    fn formats(encodes: &[VideoEncodeType]) -> Vec<Format> {
+        return encodes
+            .iter()
+            .map(|encode| Format {
+                encode: encode.clone(),
+                sizes: vec![],
+            })
+            .collect();
+    }
+
+    #[test]
+    fn test_local_capture_videorate() {
+        let configuration = |fps: u32| CaptureConfiguration {
+            encode: VideoEncodeType::H264,
+            height: 720,
+            width: 1280,
+            frame_interval: FrameInterval {
+                numerator: 1,
+                denominator: fps,
+            },
+            keyframe_interval: None,
+            encoder_threads: Some(1),
+            b_frames: None,
+            pixel_format: PixelFormat::I420,
+            allow_closest_resolution: false,
+            io_mode: None,
+        };
+        let formats = vec![
+            Format {
+                encode: VideoEncodeType::YUYV,
+                sizes: vec![Size {
+                    width: 1280,
+                    height: 720,
+                    intervals: vec![
+                        FrameInterval {
+                            numerator: 1,
+                            denominator: 60,
+                        },
+                        FrameInterval {
+                            numerator: 1,
+                            denominator: 30,
+                        },
+                    ],
+                }],
+            },
+            // Only the intervals of the captured format are used
+            Format {
+                encode: VideoEncodeType::H265,
+                sizes: vec![Size {
+                    width: 1280,
+                    height: 720,
+                    intervals: vec![FrameInterval {
+                        numerator: 1,
+                        denominator: 25,
+                    }],
+                }],
+            },
+        ];
+        let capture =
+            |fps: u32| local_capture("/dev/video0", &formats, &configuration(fps), None).unwrap();
+
+        // The device provides 30 fps, no need to drop frames
+        assert!(!capture(30).contains("videorate"));
+
+        // 25 fps is not provided, the device runs at 30 fps and videorate caps it
+        assert!(capture(25).starts_with(concat!(
+            "v4l2src device=/dev/video0",
+            " ! video/x-raw,format=YUY2,width=1280,height=720,framerate=30/1",
+            " ! videorate drop-only=true ! video/x-raw,framerate=25/1",
+            " ! videoconvert"
+        )));
+
+        // videorate can't add frames, faster framerates are still requested to the device
+        assert!(!capture(120).contains("videorate"));
+    }
+
+    #[test]
+    fn test_local_capture() {
+        let configuration = CaptureConfiguration {
+            encode: VideoEncodeType::H264,
+            height: 720,
+            width: 1280,
+            frame_interval: FrameInterval {
+                numerator: 1,
+                denominator: 30,
+            },
+            keyframe_interval: None,
+            encoder_threads: None,
+            b_frames: None,
+            pixel_format: PixelFormat::I420,
+            allow_closest_resolution: false,
+            io_mode: None,
+        };
+        let capture = |encodes: &[VideoEncodeType]| {
+            local_capture("/dev/video0", &formats(encodes), &configuration, None)
+        };
+
+        // H264 is captured directly
+        let passthrough =
+            "v4l2src device=/dev/video0 ! video/x-h264,width=1280,height=720,framerate=30/1";
+        assert_eq!(
+            capture(&[VideoEncodeType::MJPG, VideoEncodeType::H264]).unwrap(),
+            passthrough
+        );
+        assert_eq!(capture(&[]).unwrap(), passthrough);
+
+        // Raw and MJPG are transcoded
+        let pipeline = capture(&[VideoEncodeType::MJPG, VideoEncodeType::YUYV]).unwrap();
+        assert!(pipeline.starts_with(concat!(
+            "v4l2src device=/dev/video0",
+            " ! video/x-raw,format=YUY2,width=1280,height=720,framerate=30/1",
+            " ! videoconvert ! video/x-raw,format=I420 ! ",
+        )));
+        let pipeline = capture(&[VideoEncodeType::MJPG]).unwrap();
+        assert!(pipeline.starts_with(concat!(
+            "v4l2src device=/dev/video0",
+            " ! image/jpeg,width=1280,height=720,framerate=30/1 ! jpegdec",
+            " ! videoconvert ! video/x-raw,format=I420 ! ",
+        )));
+
+        // The encoder receives the configured pixel format
+        let configuration = CaptureConfiguration {
+            pixel_format: PixelFormat::NV12,
+            ..configuration.clone()
+        };
+        let pipeline = local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::YUYV]),
+            &configuration,
+            None,
+        )
+        .unwrap();
+        assert!(pipeline.contains(" ! videoconvert ! video/x-raw,format=NV12 ! "));
+
+        // Encoder options can't be used when the video is not encoded
+        assert!(local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::H264]),
+            &configuration,
+            None
+        )
+        .is_err());
+        let configuration = CaptureConfiguration {
+            pixel_format: PixelFormat::I420,
+            keyframe_interval: Some(15),
+            ..configuration.clone()
+        };
+        assert!(local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::H264]),
+            &configuration,
+            None
+        )
+        .is_err());
+        assert!(local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::YUYV]),
+            &configuration,
+            None
+        )
+        .is_ok());
+
+        // The io mode is only set when configured
+        let configuration = CaptureConfiguration {
+            keyframe_interval: None,
+            io_mode: Some(IoMode::DmabufImport),
+            ..configuration.clone()
+        };
+        assert_eq!(
+            local_capture(
+                "/dev/video0",
+                &formats(&[VideoEncodeType::H264]),
+                &configuration,
+                None
+            )
+            .unwrap(),
+            "v4l2src device=/dev/video0 io-mode=dmabuf-import ! video/x-h264,width=1280,height=720,framerate=30/1"
+        );
+        assert!(local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::YUYV]),
+            &configuration,
+            None
+        )
+        .unwrap()
+        .starts_with("v4l2src device=/dev/video0 io-mode=dmabuf-import ! video/x-raw,"));
+
+        assert!(capture(&[VideoEncodeType::H265]).is_err());
+
+        // The overlay is drawn before the conversion to the encoder format
+        let overlay = "textoverlay text=\"Potato\"";
+        assert!(local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::YUYV]),
+            &configuration,
+            Some(overlay)
+        )
+        .unwrap()
+        .contains(",framerate=30/1 ! textoverlay text=\"Potato\" ! videoconvert ! "));
+        assert!(local_capture(
+            "/dev/video0",
+            &formats(&[VideoEncodeType::H264]),
+            &configuration,
+            Some(overlay)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_crop() {
+        let resolution = Resolution {
+            width: 1280,
+            height: 720,
+        };
+        let crop = |left: u32, right: u32, top: u32, bottom: u32| {
+            crop_description(
+                &Crop {
+                    left,
+                    right,
+                    top,
+                    bottom,
+                },
+                &resolution,
+            )
+        };
+
+        assert_eq!(
+            crop(320, 320, 0, 180).unwrap(),
+            "videocrop left=320 right=320 top=0 bottom=180"
+        );
+        assert!(crop(640, 639, 0, 0).is_ok());
+        assert!(crop(640, 640, 0, 0).is_err());
+        assert!(crop(0, 0, 720, 0).is_err());
+        assert!(crop(u32::MAX, u32::MAX, 0, 0).is_err());
+
+        let base = test_stream(&["udp://192.168.0.1:5600"]);
+        let stream = VideoAndStreamInformation {
+            video_source: VideoSourceType::Gst(VideoSourceGst {
+                name: "Fake".into(),
+                source: VideoSourceGstType::Fake("ball".into()),
+            }),
+            stream_information: StreamInformation {
+                configuration: CaptureConfiguration {
+                    width: 1280,
+                    ..base.stream_information.configuration
+                },
+                overlay: Some(TextOverlay {
+                    text: "{name}".into(),
+                    show_timestamp: false,
+                    position: OverlayPosition::TopLeft,
+                }),
+                crop: Some(Crop {
+                    left: 320,
+                    right: 320,
+                    top: 0,
+                    bottom: 0,
+                }),
+                ..base.stream_information
+            },
+            ..base
+        };
+        assert!(create_udp_pipeline(&stream).unwrap().contains(
+            " ! videocrop left=320 right=320 top=0 bottom=0 ! textoverlay text=\"Test\" valignment=top halignment=left ! videoconvert ! "
+        ));
+
+        // Devices that provide H264 are not encoded, the video can't be cropped
+        let stream = VideoAndStreamInformation {
+            video_source: VideoSourceType::Local(VideoSourceLocal {
+                name: "PotatoCam".into(),
+                device_path: "/dev/video42".into(),
+                typ: VideoSourceLocalType::Unknown("TestPotatoCam".into()),
+            }),
+            ..stream
+        };
+        assert!(create_udp_pipeline(&stream).is_err());
+    }
+
+    #[test]
+    fn test_overlay() {
+        let overlay = |text: &str, show_timestamp: bool, position: OverlayPosition| {
+            overlay_description(
+                &TextOverlay {
+                    text: text.into(),
+                    show_timestamp,
+                    position,
+                },
+                "Front \"camera\"",
+            )
+        };
+
+        assert_eq!(
+            overlay("{name}", false, OverlayPosition::TopLeft).unwrap(),
+            "textoverlay text=\"Front \\\"camera\\\"\" valignment=top halignment=left"
+        );
+        assert_eq!(
+            overlay("ROV {name} -", true, OverlayPosition::BottomRight).unwrap(),
+            "clockoverlay time-format=\"%Y-%m-%d %H:%M:%S\" text=\"ROV Front \\\"camera\\\" -\" valignment=bottom halignment=right"
+        );
+        assert_eq!(
+            overlay("", true, OverlayPosition::TopRight).unwrap(),
+            "clockoverlay time-format=\"%Y-%m-%d %H:%M:%S\" text=\"\" valignment=top halignment=right"
+        );
+        assert!(overlay("", false, OverlayPosition::TopLeft).is_err());
+    }
+
+    #[test]
+    fn test_h264_encoder_description() {
+        let mut configuration = CaptureConfiguration {
+            encode: VideoEncodeType::H264,
+            height: 480,
+            width: 640,
+            frame_interval: FrameInterval {
+                numerator: 1,
+                denominator: 30,
+            },
+            keyframe_interval: None,
+            encoder_threads: Some(3),
+            b_frames: None,
+            pixel_format: PixelFormat::I420,
+            allow_closest_resolution: false,
+            io_mode: None,
+        };
+
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::X264, &configuration).unwrap(),
+            "x264enc bitrate=5000 threads=3 ! video/x-h264, profile=baseline"
+        );
+
+        configuration.keyframe_interval = Some(15);
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::X264, &configuration).unwrap(),
+            "x264enc bitrate=5000 threads=3 key-int-max=15 ! video/x-h264, profile=baseline"
+        );
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::V4l2, &configuration).unwrap(),
+            "v4l2h264enc extra-controls=\"controls,video_bitrate=5000000,h264_i_frame_period=15\" ! video/x-h264, profile=baseline"
+        );
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::Omx, &configuration).unwrap(),
+            "omxh264enc target-bitrate=5000000 control-rate=variable periodicity-idr=15 ! video/x-h264, profile=baseline"
+        );
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::NvV4l2, &configuration).unwrap(),
+            "nvvidconv ! video/x-raw(memory:NVMM) ! nvv4l2h264enc bitrate=5000000 iframeinterval=15 ! video/x-h264, profile=baseline"
+        );
+
+        // B-frames need the main profile, the baseline profile would negotiate them away
+        configuration.keyframe_interval = None;
+        configuration.b_frames = Some(2);
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::X264, &configuration).unwrap(),
+            "x264enc bitrate=5000 threads=3 bframes=2 ! video/x-h264, profile=main"
+        );
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::V4l2, &configuration).unwrap(),
+            "v4l2h264enc extra-controls=\"controls,video_bitrate=5000000,video_b_frames=2\" ! video/x-h264, profile=main"
+        );
+        assert!(h264_encoder_description(&H264Encoder::Omx, &configuration).is_err());
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::NvV4l2, &configuration).unwrap(),
+            "nvvidconv ! video/x-raw(memory:NVMM) ! nvv4l2h264enc bitrate=5000000 num-B-Frames=2 ! video/x-h264, profile=main"
+        );
+
+        configuration.b_frames = Some(0);
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::X264, &configuration).unwrap(),
+            "x264enc bitrate=5000 threads=3 bframes=0 ! video/x-h264, profile=baseline"
+        );
+        assert_eq!(
+            h264_encoder_description(&H264Encoder::Omx, &configuration).unwrap(),
+            "omxh264enc target-bitrate=5000000 control-rate=variable ! video/x-h264, profile=baseline"
+        );
+    }
+
+    #[test]
+    fn test_udp_ipv6() {
+        let result = create_stream(&test_stream(&[
+            "udp://[::1]:5600",
+            "udp://[fe80::1:2]:5601",
+        ]));
+
+        assert!(result.is_ok());
+        assert!(result
+            .unwrap()
+            .inner()
+            .pipeline()
+            .ends_with("multiudpsink clients=::1:5600,fe80::1:2:5601"));
+    }
+
+    #[test]
+    fn test_udp_clients() {
+        assert!(udp_clients(&[]).is_err());
+        assert!(udp_clients(&[Url::parse("udp://192.168.2.2").unwrap()]).is_err());
+
+        // All endpoints should be valid clients
+        assert!(udp_clients(&[
+            Url::parse("udp://192.168.2.1").unwrap(),
+            Url::parse("udp://192.168.2.2:5601").unwrap(),
+        ])
+        .is_err());
+        assert_eq!(
+            udp_clients(&[
+                Url::parse("udp://192.168.2.1:5600").unwrap(),
+                Url::parse("udp://192.168.2.2:5601").unwrap(),
+            ])
+            .unwrap(),
+            "192.168.2.1:5600,192.168.2.2:5601"
+        );
+        assert_eq!(
+            udp_clients(&[Url::parse("udp://[fe80::1]:5600").unwrap()]).unwrap(),
+            "fe80::1:5600"
+        );
+    }
+
+    #[test]
+    fn test_udp_buffer_size() {
+        let stream = |udp_buffer_size: Option<u32>| {
+            let base = test_stream(&["udp://192.168.0.1:5600"]);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    udp_buffer_size,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+
+        assert!(create_udp_pipeline(&stream(None))
+            .unwrap()
+            .ends_with("multiudpsink clients=192.168.0.1:5600"));
+        assert!(create_udp_pipeline(&stream(Some(2097152)))
+            .unwrap()
+            .ends_with("multiudpsink clients=192.168.0.1:5600 buffer-size=2097152"));
+
+        assert!(check(&stream(Some(2097152))).is_ok());
+        assert!(check(&stream(Some(0))).is_err());
+        assert!(check(&stream(Some(u32::MAX))).is_err());
+    }
+
+    #[test]
+    fn test_udp_multicast() {
+        let stream = |endpoint: &str, multicast_ttl: Option<u8>| {
+            let base = test_stream(&[endpoint]);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    multicast_ttl,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+
+        assert!(create_udp_pipeline(&stream("udp://239.0.0.1:5600", None))
+            .unwrap()
+            .ends_with("multiudpsink clients=239.0.0.1:5600 auto-multicast=true ttl-mc=1"));
+        assert!(
+            create_udp_pipeline(&stream("udp://239.0.0.1:5600", Some(4)))
+                .unwrap()
+                .ends_with("multiudpsink clients=239.0.0.1:5600 auto-multicast=true ttl-mc=4")
+        );
+        assert!(
+            !create_udp_pipeline(&stream("udp://192.168.2.1:5600", None))
+                .unwrap()
+                .contains("multicast")
+        );
+
+        assert!(check(&stream("udp://239.0.0.1:5600", Some(4))).is_ok());
+        assert!(check(&stream("udp://239.0.0.1:5600", Some(0))).is_err());
+        assert!(check(&stream("udp://192.168.2.1:5600", Some(4))).is_err());
+        assert!(check(&stream("udp://224.0.0.1:5600", None)).is_err());
+    }
+
+    #[test]
+    fn test_closest_resolution() {
+        let size = |width: u32, height: u32| Size {
+            width,
+            height,
+            intervals: vec![],
+        };
+        let sizes = vec![size(640, 480), size(1280, 720), size(1920, 1080)];
+        let mut configuration = CaptureConfiguration {
+            encode: VideoEncodeType::H264,
+            height: 1024,
+            width: 1280,
+            frame_interval: FrameInterval {
+                numerator: 1,
+                denominator: 30,
+            },
+            keyframe_interval: None,
+            encoder_threads: None,
+            b_frames: None,
+            pixel_format: PixelFormat::I420,
+            allow_closest_resolution: false,
+            io_mode: None,
+        };
+
+        // Strict by default
+        assert!(closest_resolution(&sizes, &configuration).is_err());
+        assert!(closest_resolution(&[], &configuration).is_ok());
+
+        configuration.allow_closest_resolution = true;
+        assert_eq!(
+            closest_resolution(&sizes, &configuration).unwrap(),
+            Resolution {
+                width: 1280,
+                height: 720
+            }
+        );
+
+        configuration.width = 1920;
+        configuration.height = 1080;
+        assert_eq!(
+            closest_resolution(&sizes, &configuration).unwrap(),
+            Resolution {
+                width: 1920,
+                height: 1080
+            }
+        );
+    }
+
+    #[test]
+    fn test_udp_ports() {
+        let endpoints = |endpoint: &str| vec![Url::parse(endpoint).unwrap()];
+
+        assert!(check_udp_ports(&endpoints("udp://192.168.2.1:5600")).is_ok());
+        assert!(check_udp_ports(&endpoints("udp://192.168.2.1:65535")).is_ok());
+        assert!(check_udp_ports(&endpoints("udp://192.168.2.1:0")).is_err());
+        assert!(check_udp_ports(&endpoints("udp://192.168.2.1:80")).is_err());
+        assert!(check_udp_ports(&endpoints("udp://192.168.2.1:1023")).is_err());
+
+        // Ports out of the 16 bits range are rejected when parsed
+        assert!(Url::parse("udp://192.168.2.1:65536").is_err());
+    }
+
+    #[test]
+    fn test_rtp_mtu() {
+        let stream = |rtp_mtu: Option<u32>| {
+            let base = test_stream(&["udp://192.168.0.1:5600"]);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    rtp_mtu,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+
+        assert!(!create_udp_pipeline(&stream(None)).unwrap().contains("mtu="));
+        assert!(create_udp_pipeline(&stream(Some(1200)))
+            .unwrap()
+            .contains(" ! rtph264pay config-interval=10 pt=96 mtu=1200 ! multiudpsink"));
+
+        assert!(check(&stream(Some(200))).is_ok());
+        assert!(check(&stream(Some(9000))).is_ok());
+        assert!(check(&stream(Some(199))).is_err());
+        assert!(check(&stream(Some(9001))).is_err());
+    }
+
+    #[test]
+    fn test_rtcp() {
+        let stream = |endpoints: Vec<&str>, rtcp: bool| {
+            let base = test_stream(&endpoints);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    rtcp,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+
+        let pipeline = create_udp_pipeline(&stream(vec!["udp://192.168.0.1:5600"], false)).unwrap();
+        assert!(!pipeline.contains("rtpbin"));
+        assert!(pipeline.ends_with(" pt=96 ! multiudpsink clients=192.168.0.1:5600"));
+
+        let pipeline = create_udp_pipeline(&stream(
+            vec!["udp://192.168.0.1:5600", "udp://192.168.0.2:5602"],
+            true,
+        ))
+        .unwrap();
+        assert!(pipeline.ends_with(concat!(
+            " pt=96 ! rtpbin.send_rtp_sink_0 rtpbin name=rtpbin rtpbin.send_rtp_src_0",
+            " ! multiudpsink clients=192.168.0.1:5600,192.168.0.2:5602",
+            " rtpbin.send_rtcp_src_0 ! multiudpsink name=rtcp_sink clients=192.168.0.1:5601,192.168.0.2:5603 sync=false async=false"
+        )));
+        let elements = pipeline_elements(&pipeline);
+        assert_eq!(
+            elements[elements.len() - 3..],
+            ["rtph264pay", "rtpbin", "multiudpsink"]
+        );
+
+        assert!(create_udp_pipeline(&stream(vec!["udp://239.0.0.1:5600"], true))
+            .unwrap()
+            .ends_with(" ! multiudpsink name=rtcp_sink clients=239.0.0.1:5601 sync=false async=false auto-multicast=true ttl-mc=1"));
+
+        assert!(check(&stream(vec!["udp://192.168.0.1:65534"], true)).is_ok());
+        assert!(check(&stream(vec!["udp://192.168.0.1:65535"], false)).is_ok());
+        assert!(check(&stream(vec!["udp://192.168.0.1:65535"], true)).is_err());
+    }
+
+    #[test]
+    fn test_missing_elements() {
+        let stream = test_stream(&["udp://192.168.0.1:5600"]);
+        let pipeline = create_udp_pipeline(&stream).unwrap();
+
+        assert_eq!(
+            pipeline_elements(&pipeline),
+            vec![
+                "v4l2src",
+                "h264parse",
+                "queue",
+                "rtph264pay",
+                "multiudpsink"
+            ]
+        );
+        assert!(check_missing_elements(&stream, &pipeline, |_| true).is_ok());
+        assert_eq!(
+            check_missing_elements(&stream, &pipeline, |name| name != "rtph264pay")
+                .unwrap_err()
+                .as_str(),
+            "H264 UDP streaming requires the 'rtph264pay' element, which is not installed"
+        );
+        assert_eq!(
+            check_missing_elements(&stream, &pipeline, |name| name == "v4l2src")
+                .unwrap_err()
+                .as_str(),
+            "H264 UDP streaming requires the 'h264parse', 'queue', 'rtph264pay' and 'multiudpsink' elements, which are not installed"
+        );
+    }
+
+    #[test]
+    fn test_udp_endpoint_options() {
+        let stream = |endpoints: Vec<&str>, multicast_ttl: Option<u8>| {
+            let base = test_stream(&endpoints);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    multicast_ttl,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+        let pipeline = |endpoints: Vec<&str>, multicast_ttl: Option<u8>| {
+            create_udp_pipeline(&stream(endpoints, multicast_ttl)).unwrap()
+        };
+
+        assert!(
+            pipeline(vec!["udp://192.168.2.2:5600?pt=97"], None).ends_with(
+                " ! rtph264pay config-interval=10 pt=97 ! multiudpsink clients=192.168.2.2:5600"
+            )
+        );
+        assert!(pipeline(
+            vec!["udp://192.168.2.2:5600", "udp://192.168.2.3:5600?pt=100"],
+            None
+        )
+        .ends_with(" pt=100 ! multiudpsink clients=192.168.2.2:5600,192.168.2.3:5600"));
+        assert!(
+            pipeline(vec!["udp://239.0.0.1:5600?ttl=2&pt=98"], Some(5)).ends_with(
+                " pt=98 ! multiudpsink clients=239.0.0.1:5600 auto-multicast=true ttl-mc=2"
+            )
+        );
+        assert!(pipeline(vec!["udp://239.0.0.1:5600"], Some(5)).ends_with(
+            " pt=96 ! multiudpsink clients=239.0.0.1:5600 auto-multicast=true ttl-mc=5"
+        ));
+        assert!(pipeline(vec!["udp://192.168.2.2:5600?potato=1"], None)
+            .ends_with(" pt=96 ! multiudpsink clients=192.168.2.2:5600"));
+
+        let rtp = rtp_parameters(&stream(vec!["udp://192.168.2.2:5600"], None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rtp.media, "video");
+        assert_eq!(rtp.encoding_name, "H264");
+        assert_eq!(rtp.clock_rate, 90000);
+        assert_eq!(rtp.payload_type, 96);
+        assert_eq!(
+            rtp_parameters(&stream(vec!["udp://192.168.2.2:5600?pt=97"], None))
+                .unwrap()
+                .unwrap()
+                .payload_type,
+            97
+        );
+        assert_eq!(
+            rtp_parameters(&stream(vec!["srt://192.168.2.2:5600"], None)).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            sdp(&stream(
+                vec!["udp://192.168.2.2:5600?pt=97", "udp://192.168.2.3:5602"],
+                None
+            ))
+            .unwrap()
+            .unwrap(),
+            concat!(
+                "v=0\r\n",
+                "o=- 0 0 IN IP4 127.0.0.1\r\n",
+                "s=Test\r\n",
+                "c=IN IP4 192.168.2.2\r\n",
+                "t=0 0\r\n",
+                "m=video 5600 RTP/AVP 97\r\n",
+                "a=rtpmap:97 H264/90000\r\n",
+                "a=fmtp:97 packetization-mode=1\r\n"
+            )
+        );
+        assert!(sdp(&stream(vec!["udp://239.0.0.1:5600"], Some(5)))
+            .unwrap()
+            .unwrap()
+            .contains("\r\nc=IN IP4 239.0.0.1/5\r\n"));
+        assert!(sdp(&stream(vec!["udp://[ff02::1]:5600"], None))
+            .unwrap()
+            .unwrap()
+            .contains("\r\nc=IN IP6 ff02::1\r\n"));
+        assert_eq!(
+            sdp(&stream(vec!["srt://192.168.2.2:5600"], None)).unwrap(),
+            None
+        );
+        // Invalid UDP streams are reported instead of being described as not UDP
+        assert!(sdp(&stream(vec!["udp://239.0.0.1:5600?ttl=potato"], None)).is_err());
+
+        assert!(check(&stream(vec!["udp://192.168.2.2:5600?pt=95"], None)).is_err());
+        assert!(check(&stream(vec!["udp://192.168.2.2:5600?pt=potato"], None)).is_err());
+        assert!(check(&stream(vec!["udp://192.168.2.2:5600?ttl=2"], None)).is_err());
+        assert!(check(&stream(vec!["udp://239.0.0.1:5600?ttl=0"], None)).is_err());
+        // Only the TTL that is used is validated, the ttl option is used over the stream one
+        assert!(check(&stream(vec!["udp://239.0.0.1:5600?ttl=0"], Some(4))).is_err());
+        assert!(check(&stream(vec!["udp://239.0.0.1:5600?ttl=2"], Some(0))).is_ok());
+        assert!(check(&stream(
+            vec![
+                "udp://192.168.2.2:5600?pt=97",
+                "udp://192.168.2.3:5600?pt=98"
+            ],
+            None
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_duplicated_endpoints() {
+        let endpoints = |endpoints: &[&str]| -> Vec<Url> {
+            endpoints
+                .iter()
+                .map(|endpoint| Url::parse(endpoint).unwrap())
+                .collect()
+        };
+
+        assert!(check_duplicated_endpoints(&endpoints(&[
+            "udp://192.168.2.1:5600",
+            "udp://192.168.2.1:5601",
+            "udp://192.168.2.2:5600",
+        ]))
+        .is_ok());
+        assert!(check_duplicated_endpoints(&endpoints(&[
+            "udp://192.168.2.1:5600",
+            "udp://192.168.2.2:5600",
+            "udp://192.168.2.1:5600",
+        ]))
+        .is_err());
+        // The options don't change the destination of the packets
+        assert!(check_duplicated_endpoints(&endpoints(&[
+            "udp://192.168.2.1:5600?pt=97",
+            "udp://192.168.2.1:5600?ttl=4",
+        ]))
+        .is_err());
+        assert!(check_duplicated_endpoints(&endpoints(&[
+            "file:///tmp/recording.mkv",
+            "file:///tmp/recording.mkv?potato",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_config_interval() {
+        let stream = |config_interval: Option<i32>| {
+            let base = test_stream(&["udp://192.168.0.1:5600"]);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    config_interval,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+
+        assert!(create_udp_pipeline(&stream(None))
+            .unwrap()
+            .contains(" ! rtph264pay config-interval=10 pt=96"));
+        assert!(create_udp_pipeline(&stream(Some(-1)))
+            .unwrap()
+            .contains(" ! rtph264pay config-interval=-1 pt=96"));
+
+        assert!(check(&stream(Some(-1))).is_ok());
+        assert!(check(&stream(Some(0))).is_ok());
+        assert!(check(&stream(Some(-2))).is_err());
+        assert!(check(&stream(Some(3601))).is_err());
+    }
+
+    #[test]
+    fn test_file() {
+        let directory = std::env::temp_dir();
+        let stream = |endpoints: Vec<Url>, segment_duration: Option<u32>| {
+            let base = test_stream(&[]);
+            VideoAndStreamInformation {
+                stream_information: StreamInformation {
+                    endpoints,
+                    segment_duration,
+                    ..base.stream_information
+                },
+                ..base
+            }
+        };
+        let file = Url::from_file_path(directory.join("recording.mkv")).unwrap();
+
+        let result = new(&stream(vec![file.clone()], None));
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().inner().pipeline(), format!("v4l2src device=/dev/video42 ! video/x-h264,width=1080,height=720,framerate=30/1 ! h264parse ! queue ! matroskamux ! filesink location={}", directory.join("recording.mkv").display()));
+
+        assert!(create_file_pipeline(&stream(vec![file.clone()], Some(60)))
+            .unwrap()
+            .ends_with(&format!(
+                " ! h264parse ! queue ! splitmuxsink muxer=matroskamux location={} max-size-time=60000000000",
+                directory.join("recording_%05d.mkv").display()
+            )));
+
+        assert!(check(&stream(vec![file.clone()], Some(0))).is_err());
+        assert!(check(&stream(vec![file.clone(), file.clone()], None)).is_err());
+        assert!(check(&stream(
+            vec![Url::from_file_path(directory.join("recording.mp4")).unwrap()],
+            None
+        ))
+        .is_err());
+        assert!(check(&stream(
+            vec![Url::parse("file:///potato/recording.mkv").unwrap()],
+            None
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_srt() {
+        let stream = |endpoints: Vec<&str>| test_stream(&endpoints);
+
+        assert_eq!(
+            create_srt_pipeline(&stream(vec!["srt://192.168.2.1:8888"])).unwrap(),
+            "v4l2src device=/dev/video42 ! video/x-h264,width=1080,height=720,framerate=30/1 ! h264parse config-interval=-1 ! queue ! mpegtsmux alignment=7 ! srtsink uri=srt://192.168.2.1:8888"
+        );
+        assert!(create_srt_pipeline(&stream(vec![
+            "srt://0.0.0.0:8888?latency=200&passphrase=potatopotato&mode=listener"
+        ]))
+        .unwrap()
+        .ends_with(" ! srtsink uri=srt://0.0.0.0:8888 latency=200 passphrase=\"potatopotato\" mode=listener"));
+
+        assert_eq!(
+            redacted_pipeline(
+                "mpegtsmux ! srtsink uri=srt://0.0.0.0:8888 passphrase=\"potatopotato\" mode=listener"
+            ),
+            "mpegtsmux ! srtsink uri=srt://0.0.0.0:8888 passphrase=\"*****\" mode=listener"
+        );
+        assert_eq!(
+            redacted_endpoint(
+                &Url::parse("srt://0.0.0.0:8888?latency=200&passphrase=potatopotato").unwrap()
+            )
+            .as_str(),
+            "srt://0.0.0.0:8888?latency=200&passphrase=*****"
+        );
+        assert_eq!(
+            redacted_endpoint(&Url::parse("srt://192.168.2.1:8888").unwrap()).as_str(),
+            "srt://192.168.2.1:8888"
+        );
+
+        assert!(srt_sink(&Url::parse("srt://192.168.2.1").unwrap()).is_err());
+        assert!(srt_sink(&Url::parse("srt://192.168.2.1:8888?latency=-1").unwrap()).is_err());
+        assert!(
+            srt_sink(&Url::parse("srt://192.168.2.1:8888?passphrase=potato").unwrap()).is_err()
+        );
+        assert!(srt_sink(&Url::parse("srt://192.168.2.1:8888?mode=rendezvous").unwrap()).is_err());
+        assert!(srt_sink(&Url::parse("srt://192.168.2.1:8888?streamid=potato").unwrap()).is_err());
+        assert!(check(&stream(vec![
+            "srt://192.168.2.1:8888",
+            "srt://192.168.2.2:8888"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_rtp_identity() {
+        let stream =
+            |ssrc: Option<u32>, seqnum_offset: Option<u16>, timestamp_offset: Option<u32>| {
+                let base = test_stream(&["udp://192.168.0.1:5600"]);
+                VideoAndStreamInformation {
+                    stream_information: StreamInformation {
+                        ssrc,
+                        seqnum_offset,
+                        timestamp_offset,
+                        ..base.stream_information
+                    },
+                    ..base
+                }
+            };
+
+        assert!(create_udp_pipeline(&stream(None, None, None))
+            .unwrap()
+            .contains(" ! rtph264pay config-interval=10 pt=96 ! multiudpsink"));
+        assert!(create_udp_pipeline(&stream(Some(3735928559), None, None))
+            .unwrap()
+            .contains(" ! rtph264pay config-interval=10 pt=96 ssrc=3735928559 ! multiudpsink"));
+        assert!(create_udp_pipeline(&stream(Some(42), Some(0), Some(1000)))
+            .unwrap()
+            .contains(" pt=96 ssrc=42 seqnum-offset=0 timestamp-offset=1000 ! multiudpsink"));
     }
 }
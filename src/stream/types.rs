@@ -1,6 +1,6 @@
 use super::stream_backend::StreamBackend;
-use super::video_stream_udp::VideoStreamUdp;
-use crate::video::types::CaptureConfiguration;
+use super::video_stream_pipeline::VideoStreamPipeline;
+use crate::video::types::{CaptureConfiguration, FrameInterval};
 use crate::video_stream::types::VideoAndStreamInformation;
 
 use serde::{Deserialize, Serialize};
@@ -8,19 +8,27 @@ use url::Url;
 
 #[derive(Debug)]
 pub enum StreamType {
-    UDP(VideoStreamUdp),
+    UDP(VideoStreamPipeline),
+    // Recording to a local file, the pipeline is drained when stopped to finalize the file
+    FILE(VideoStreamPipeline),
+    // MPEG-TS over SRT
+    SRT(VideoStreamPipeline),
 }
 
 impl StreamType {
     pub fn inner(&self) -> &(dyn StreamBackend + '_) {
         match self {
             StreamType::UDP(backend) => backend,
+            StreamType::FILE(backend) => backend,
+            StreamType::SRT(backend) => backend,
         }
     }
 
     pub fn mut_inner(&mut self) -> &mut (dyn StreamBackend + '_) {
         match self {
             StreamType::UDP(backend) => backend,
+            StreamType::FILE(backend) => backend,
+            StreamType::SRT(backend) => backend,
         }
     }
 }
@@ -29,10 +37,159 @@ impl StreamType {
 pub struct StreamInformation {
     pub endpoints: Vec<Url>,
     pub configuration: CaptureConfiguration,
+    // Socket send buffer size in bytes used by UDP streams, the system default is used if not set.
+    // The kernel limits it to net.core.wmem_max, that should be increased for bigger buffers
+    #[serde(default)]
+    pub udp_buffer_size: Option<u32>,
+    // Time to live of multicast UDP packets, 1 is used if not set to keep the packets in the local network
+    #[serde(default)]
+    pub multicast_ttl: Option<u8>,
+    // Interval in seconds to send SPS and PPS in-band with the video, -1 sends them with every keyframe
+    // and 0 disables it, 10 is used if not set
+    #[serde(default)]
+    pub config_interval: Option<i32>,
+    // Duration in seconds of each file when recording, a single file is created if not set
+    #[serde(default)]
+    pub segment_duration: Option<u32>,
+    // RTP SSRC and initial sequence number and timestamp of UDP streams, random values are used if not set.
+    // Fixed values keep the stream identity across restarts, as required by some SIP and recording setups
+    #[serde(default)]
+    pub ssrc: Option<u32>,
+    #[serde(default)]
+    pub seqnum_offset: Option<u16>,
+    #[serde(default)]
+    pub timestamp_offset: Option<u32>,
+    // Maximum size of the RTP packets of UDP streams, smaller values avoid fragmentation over
+    // VPNs and tunnels. The payloader default (1400) is used if not set
+    #[serde(default)]
+    pub rtp_mtu: Option<u32>,
+    // Text burned into the video, only available when the video is encoded by the stream
+    #[serde(default)]
+    pub overlay: Option<TextOverlay>,
+    // Region of the video that is streamed, only available when the video is encoded by the stream
+    #[serde(default)]
+    pub crop: Option<Crop>,
+    // Send RTCP sender reports with UDP streams, to the port after the RTP port of each endpoint.
+    // Receivers use them to compute jitter and loss and to synchronize the timestamps
+    #[serde(default)]
+    pub rtcp: bool,
+}
+
+// Pixels removed from each side of the captured video
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Crop {
+    #[serde(default)]
+    pub left: u32,
+    #[serde(default)]
+    pub right: u32,
+    #[serde(default)]
+    pub top: u32,
+    #[serde(default)]
+    pub bottom: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TextOverlay {
+    // {name} is replaced by the stream name
+    #[serde(default)]
+    pub text: String,
+    // Local date and time, shown after the text
+    #[serde(default)]
+    pub show_timestamp: bool,
+    #[serde(default)]
+    pub position: OverlayPosition,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NegotiatedCaps {
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame_interval: FrameInterval,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct StreamStatus {
     pub running: bool,
     pub video_and_stream: VideoAndStreamInformation,
+    // Resolution used to capture, can differ from the configured one if the closest resolution is allowed
+    pub resolution: Option<Resolution>,
+    pub negotiated_caps: Option<NegotiatedCaps>,
+    // Addresses where the stream can be played
+    pub uris: Vec<Url>,
+    // Number of times the pipeline queues got full, frames are dropped when it increases
+    pub queue_overruns: u64,
+    // The device of the local source was unplugged, the stream starts again when it's back
+    pub source_disconnected: bool,
+    // Number of times the pipeline failed in a row, the stream is stopped after too many
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    // Streams started with the service are unhealthy until they play
+    pub healthy: bool,
+    // The stream did not play in the last runs of the service, so it was not started.
+    // It's started again when it's enabled
+    pub held_back: bool,
+    // Parameters of the RTP packets, only available for UDP streams
+    pub rtp: Option<RtpParameters>,
+}
+
+// Parameters used by the receivers to describe an RTP stream, as in the rtpmap of a SDP file
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RtpParameters {
+    pub media: String,
+    pub encoding_name: String,
+    pub clock_rate: u32,
+    pub payload_type: u8,
+}
+
+// Current state of a stream exported as metrics
+#[derive(Debug)]
+pub struct StreamMetrics {
+    pub name: String,
+    // Kind of sink used by the stream, as the endpoint scheme
+    pub sink: String,
+    pub running: bool,
+    // State of the GStreamer pipeline, None if no pipeline exists
+    pub pipeline_state: Option<String>,
+    // Bytes sent by the multiudpsink elements, None if the pipeline has none
+    pub bytes_sent: Option<u64>,
+    pub queue_overruns: u64,
+}
+
+// Names of the streams changed by a reload of the settings
+#[derive(Debug, Default, Serialize)]
+pub struct StreamsReload {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    // Streams with a different configuration, they are created again
+    pub restarted: Vec<String>,
+    // Streams with the same configuration, they keep running untouched
+    pub kept: Vec<String>,
+}
+
+// Changes of the streams, sent to the clients that follow the streams state
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StreamEvent {
+    // Current state of all streams
+    Streams { streams: Vec<StreamStatus> },
+    Added { stream: StreamStatus },
+    Removed { name: String },
+    Updated { stream: StreamStatus },
 }
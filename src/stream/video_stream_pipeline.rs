@@ -0,0 +1,775 @@
+use super::gst::pipeline_runner::Pipeline;
+use super::stream_backend::{StreamBackend, RTCP_SINK_NAME};
+use super::types::{NegotiatedCaps, Resolution};
+use crate::cli;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use gstreamer;
+use gstreamer::prelude::*;
+use log::*;
+use simple_error::SimpleError;
+
+const OVERRUN_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const MAX_OVERRUNS_PER_INTERVAL: u64 = 10;
+
+// The delay between restarts doubles after each consecutive failure of the pipeline,
+// up to the maximum delay of the command line
+const MIN_RESTART_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+// A pipeline that ran for this long is considered healthy, and the failures are forgotten
+const MIN_HEALTHY_RUN: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Debug)]
+struct VideoStreamPipelineState {
+    // move run kill restart logic to enum as states
+    run: bool,
+    kill: bool,
+    pipeline: Pipeline,
+    // Running gstreamer pipeline, used to change it while playing
+    gst_pipeline: Option<gstreamer::Element>,
+    negotiated_caps: Option<NegotiatedCaps>,
+    // The running pipeline reached the playing state
+    playing: bool,
+    // Notified with the state callback, used to wait for the pipeline to play
+    state_changed: Arc<Condvar>,
+    // Number of times the pipeline queues got full since the stream was created,
+    // shared with the streaming threads that emit the overrun signal
+    queue_overruns: Arc<AtomicU64>,
+    // Device of local sources, the pipeline is only started while it exists
+    source_device: Option<String>,
+    source_disconnected: bool,
+    // Number of times the pipeline failed in a row, and the reason of the last failure
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    playing_callback: Option<Callback>,
+    // Called when the pipeline starts, stops, fails or reaches the playing state
+    state_callback: Option<Callback>,
+    // Send EOS and wait for it before stopping, muxers need it to finalize the files
+    drain_on_stop: bool,
+}
+
+#[derive(Clone)]
+struct Callback(Arc<dyn Fn() + Send + Sync>);
+
+impl std::fmt::Debug for Callback {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("Callback")
+    }
+}
+
+#[derive(Debug)]
+pub struct VideoStreamPipeline {
+    state: Arc<Mutex<VideoStreamPipelineState>>,
+    resolution: Option<Resolution>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    thread_rx_channel: std::sync::mpsc::Receiver<String>,
+}
+
+impl Default for VideoStreamPipelineState {
+    fn default() -> Self {
+        Self {
+            run: false,
+            kill: false,
+            pipeline: Default::default(),
+            gst_pipeline: None,
+            negotiated_caps: None,
+            playing: false,
+            state_changed: Default::default(),
+            queue_overruns: Default::default(),
+            source_device: None,
+            source_disconnected: false,
+            consecutive_failures: 0,
+            last_error: None,
+            playing_callback: None,
+            state_callback: None,
+            drain_on_stop: false,
+        }
+    }
+}
+
+impl Default for VideoStreamPipeline {
+    fn default() -> Self {
+        let state: Arc<Mutex<VideoStreamPipelineState>> = Default::default();
+        let thread_state = state.clone();
+        let (sender, receiver) = std::sync::mpsc::channel::<String>();
+        Self {
+            state,
+            resolution: None,
+            thread: Some(thread::spawn(move || {
+                run_video_stream_pipeline(thread_state.clone(), sender)
+            })),
+            thread_rx_channel: receiver,
+        }
+    }
+}
+
+impl Drop for VideoStreamPipeline {
+    fn drop(&mut self) {
+        // Kill the thread and wait for it
+        self.state.lock().unwrap().kill = true;
+        let answer = self.thread.take().unwrap().join();
+        debug!("done: {:#?}", answer);
+    }
+}
+
+impl VideoStreamPipeline {
+    pub fn set_source_device(&mut self, device: &str) {
+        self.state.lock().unwrap().source_device = Some(device.to_string());
+    }
+
+    pub fn set_drain_on_stop(&mut self, drain: bool) {
+        self.state.lock().unwrap().drain_on_stop = drain;
+    }
+
+    // Change the clients of the running multiudpsink without restarting the pipeline,
+    // the RTCP clients are only changed when the stream sends RTCP
+    pub fn set_clients(
+        &mut self,
+        clients: &str,
+        rtcp_clients: Option<&str>,
+    ) -> Result<(), SimpleError> {
+        let state = self.state.lock().unwrap();
+        let pipeline = match &state.gst_pipeline {
+            Some(pipeline) => pipeline,
+            // Nothing is running, the new description will be used in the next start
+            None => return Ok(()),
+        };
+
+        let sink = match multiudpsink(pipeline) {
+            Some(sink) => sink,
+            None => return Err(SimpleError::new("Pipeline does not have a multiudpsink.")),
+        };
+        let mut sinks = vec![(sink, clients)];
+        if let (Some(sink), Some(rtcp_clients)) = (rtcp_sink(pipeline), rtcp_clients) {
+            sinks.push((sink, rtcp_clients));
+        }
+
+        for (sink, clients) in sinks {
+            if let Err(error) = sink.set_property("clients", &clients) {
+                return Err(SimpleError::new(format!(
+                    "Failed to set multiudpsink clients: {}",
+                    error
+                )));
+            }
+        }
+        return Ok(());
+    }
+}
+
+// Sink of the RTP packets, the RTCP sink is also a multiudpsink
+fn multiudpsink(pipeline: &gstreamer::Element) -> Option<gstreamer::Element> {
+    return pipeline
+        .downcast_ref::<gstreamer::Bin>()?
+        .get_children()
+        .into_iter()
+        .find(|element| match element.get_factory() {
+            Some(factory) => {
+                factory.get_name() == "multiudpsink" && element.get_name() != RTCP_SINK_NAME
+            }
+            None => false,
+        });
+}
+
+// Sink of the RTCP sender reports, only available when the stream sends RTCP
+fn rtcp_sink(pipeline: &gstreamer::Element) -> Option<gstreamer::Element> {
+    return pipeline
+        .downcast_ref::<gstreamer::Bin>()?
+        .get_by_name(RTCP_SINK_NAME);
+}
+
+impl StreamBackend for VideoStreamPipeline {
+    fn start(&mut self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.run = true;
+        // Give the pipeline a new chance, even if it failed too many times before
+        state.consecutive_failures = 0;
+        return true;
+    }
+
+    fn stop(&mut self) -> bool {
+        self.state.lock().unwrap().run = false;
+        return true;
+    }
+
+    fn restart(&mut self) {
+        unimplemented!();
+    }
+
+    fn is_running(&self) -> bool {
+        return self.state.lock().unwrap().run;
+    }
+
+    fn set_pipeline_description(&mut self, description: &str) {
+        self.state.lock().unwrap().pipeline.description = description.to_string();
+    }
+
+    fn pipeline(&self) -> String {
+        let string = self.state.lock().unwrap().pipeline.description.clone();
+        return string;
+    }
+
+    fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = Some(resolution);
+    }
+
+    fn resolution(&self) -> Option<Resolution> {
+        return self.resolution.clone();
+    }
+
+    fn negotiated_caps(&self) -> Option<NegotiatedCaps> {
+        return self.state.lock().unwrap().negotiated_caps.clone();
+    }
+
+    // GraphViz representation of the running pipeline
+    fn pipeline_graph(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let bin = state
+            .gst_pipeline
+            .as_ref()?
+            .downcast_ref::<gstreamer::Bin>()?;
+        return Some(
+            gstreamer::debug_bin_to_dot_data(bin, gstreamer::DebugGraphDetails::all()).to_string(),
+        );
+    }
+
+    // Current state of the running pipeline
+    fn pipeline_state(&self) -> Option<gstreamer::State> {
+        let state = self.state.lock().unwrap();
+        let (_, current, _) = state
+            .gst_pipeline
+            .as_ref()?
+            .get_state(gstreamer::ClockTime::from_mseconds(0));
+        return Some(current);
+    }
+
+    fn queue_overruns(&self) -> u64 {
+        return self
+            .state
+            .lock()
+            .unwrap()
+            .queue_overruns
+            .load(Ordering::Relaxed);
+    }
+
+    fn source_disconnected(&self) -> bool {
+        return self.state.lock().unwrap().source_disconnected;
+    }
+
+    fn consecutive_failures(&self) -> u32 {
+        return self.state.lock().unwrap().consecutive_failures;
+    }
+
+    fn last_error(&self) -> Option<String> {
+        return self.state.lock().unwrap().last_error.clone();
+    }
+
+    // Drop the data inside the running pipeline and request a new keyframe from the encoder,
+    // elements that do not handle these events ignore them and a restart is necessary
+    fn flush(&self) -> Option<bool> {
+        let state = self.state.lock().unwrap();
+        let pipeline = state.gst_pipeline.as_ref()?;
+        // The flush should always be stopped, even if it was not started everywhere
+        let flush_started = pipeline.send_event(gstreamer::event::FlushStart::new());
+        let flush_stopped = pipeline.send_event(gstreamer::event::FlushStop::new(true));
+        let keyframe_requested = pipeline.send_event(gstreamer::event::CustomUpstream::new(
+            gstreamer::Structure::builder("GstForceKeyUnit")
+                .field("all-headers", &true)
+                .build(),
+        ));
+        return Some(flush_started && flush_stopped && keyframe_requested);
+    }
+
+    fn set_playing_callback(&mut self, callback: Box<dyn Fn() + Send + Sync>) {
+        self.state.lock().unwrap().playing_callback = Some(Callback(callback.into()));
+    }
+
+    fn set_state_callback(&mut self, callback: Box<dyn Fn() + Send + Sync>) {
+        self.state.lock().unwrap().state_callback = Some(Callback(callback.into()));
+    }
+
+    fn wait_playing(&self, timeout: std::time::Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let state_changed = state.state_changed.clone();
+        // Stops waiting when the stream is stopped, E.g: the pipeline failed too many times
+        let (state, _) = state_changed
+            .wait_timeout_while(state, timeout, |state| state.run && !state.playing)
+            .unwrap();
+        return state.playing;
+    }
+
+    // Bytes sent by the sinks of the running pipeline that keep track of it
+    fn bytes_sent(&self) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        let bytes: Vec<u64> = state
+            .gst_pipeline
+            .as_ref()?
+            .downcast_ref::<gstreamer::Bin>()?
+            .get_children()
+            .into_iter()
+            .filter_map(|element| {
+                element
+                    .get_property("bytes-served")
+                    .ok()?
+                    .get_some::<u64>()
+                    .ok()
+            })
+            .collect();
+        // Pipelines without a sink that counts the bytes, like file recordings, don't report them
+        if bytes.is_empty() {
+            return None;
+        }
+        return Some(bytes.iter().sum());
+    }
+}
+
+// Get the caps negotiated by the pipeline source, that can differ from the requested ones
+fn negotiated_caps(pipeline: &gstreamer::Element) -> Option<NegotiatedCaps> {
+    let source = pipeline
+        .downcast_ref::<gstreamer::Bin>()?
+        .get_children()
+        .into_iter()
+        .find(|element| element.get_sink_pads().is_empty())?;
+    let caps = source.get_static_pad("src")?.get_current_caps()?;
+    let structure = caps.get_structure(0)?;
+    let framerate = structure
+        .get_some::<gstreamer::Fraction>("framerate")
+        .ok()?;
+
+    return Some(NegotiatedCaps {
+        format: structure
+            .get::<&str>("format")
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| structure.get_name())
+            .to_string(),
+        width: structure.get_some::<i32>("width").ok()? as u32,
+        height: structure.get_some::<i32>("height").ok()? as u32,
+        frame_interval: framerate.into(),
+    });
+}
+
+// Count the overruns of all queues, a full queue means that the pipeline can't keep up
+// and frames are being dropped
+fn watch_queue_overruns(pipeline: &gstreamer::Element, queue_overruns: &Arc<AtomicU64>) {
+    let queues = match pipeline.downcast_ref::<gstreamer::Bin>() {
+        Some(bin) => bin.get_children(),
+        None => return,
+    };
+
+    for queue in queues.iter().filter(|element| match element.get_factory() {
+        Some(factory) => factory.get_name() == "queue",
+        None => false,
+    }) {
+        let queue_overruns = queue_overruns.clone();
+        if let Err(error) = queue.connect("overrun", false, move |_| {
+            queue_overruns.fetch_add(1, Ordering::Relaxed);
+            None
+        }) {
+            warn!("Failed to watch queue overruns: {}", error);
+        }
+    }
+}
+
+// Send EOS and wait for it to reach the sinks, muxers need it to finalize the files
+fn finish_pipeline(pipeline: &gstreamer::Element, bus: &gstreamer::Bus) {
+    if !pipeline.send_event(gstreamer::event::Eos::new()) {
+        warn!("Failed to send EOS to the pipeline.");
+        return;
+    }
+
+    let message = bus.timed_pop_filtered(
+        gstreamer::ClockTime::from_seconds(5),
+        &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+    );
+    if message.is_none() {
+        warn!("Timeout waiting for the pipeline to finish.");
+    }
+}
+
+// Delay before starting the pipeline again, after a number of consecutive failures
+fn restart_delay(consecutive_failures: u32, max_delay: std::time::Duration) -> std::time::Duration {
+    let exponent = consecutive_failures.saturating_sub(1).min(31);
+    return MIN_RESTART_DELAY
+        .checked_mul(1 << exponent)
+        .unwrap_or(max_delay)
+        .min(max_delay);
+}
+
+// The state should not be locked, the callback may access the stream
+fn notify_state_changed(state: &Arc<Mutex<VideoStreamPipelineState>>) {
+    let state_callback = {
+        let state = state.lock().unwrap();
+        state.state_changed.notify_all();
+        state.state_callback.clone()
+    };
+    if let Some(Callback(callback)) = state_callback {
+        callback();
+    }
+}
+
+// Register a failure of the pipeline and wait before it's started again,
+// the stream is stopped if the pipeline keeps failing
+fn pipeline_failed(
+    state: &Arc<Mutex<VideoStreamPipelineState>>,
+    error: String,
+    running_time: std::time::Duration,
+) {
+    let delay = {
+        let mut state = state.lock().unwrap();
+        if running_time >= MIN_HEALTHY_RUN {
+            state.consecutive_failures = 0;
+        }
+        state.consecutive_failures += 1;
+        state.last_error = Some(error.clone());
+
+        if state.consecutive_failures >= cli::manager::max_pipeline_failures() {
+            error!(
+                "Pipeline failed {} times in a row, stopping stream: {}",
+                state.consecutive_failures, error
+            );
+            state.run = false;
+            None
+        } else {
+            let delay = restart_delay(
+                state.consecutive_failures,
+                cli::manager::max_restart_delay(),
+            );
+            warn!(
+                "Pipeline failed ({} in a row), restarting in {} ms: {}",
+                state.consecutive_failures,
+                delay.as_millis(),
+                error
+            );
+            Some(delay)
+        }
+    };
+
+    notify_state_changed(state);
+    let delay = match delay {
+        Some(delay) => delay,
+        None => return,
+    };
+
+    // Sleep in small steps, the stream can be removed or stopped meanwhile
+    let started = std::time::Instant::now();
+    while started.elapsed() < delay {
+        {
+            let state = state.lock().unwrap();
+            if state.kill || !state.run {
+                return;
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100).min(delay));
+    }
+}
+
+fn run_video_stream_pipeline(
+    state: Arc<Mutex<VideoStreamPipelineState>>,
+    channel: std::sync::mpsc::Sender<String>,
+) {
+    if let Err(error) = gstreamer::init() {
+        let _ = channel.send(format!("Failed to init GStreamer: {}", error));
+        return;
+    }
+
+    let mut pipeline: Option<gstreamer::Element> = None;
+    //TODO: move to while not kill
+    'externalLoop: loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        if state.lock().unwrap().kill {
+            break 'externalLoop;
+        }
+        if !state.lock().unwrap().run {
+            continue;
+        }
+
+        // Unplugged cameras would make the pipeline fail again right away,
+        // wait for the device to be back in the same path to start it again
+        let source_device = state.lock().unwrap().source_device.clone();
+        if let Some(device) = source_device {
+            let disconnected = !std::path::Path::new(&device).exists();
+            let mut state = state.lock().unwrap();
+            if disconnected != state.source_disconnected {
+                state.source_disconnected = disconnected;
+                if disconnected {
+                    warn!("Source {} disconnected, waiting for it to be back.", device);
+                } else {
+                    info!("Source {} is back, starting pipeline.", device);
+                }
+            }
+            if disconnected {
+                continue;
+            }
+        }
+
+        let pipeline_description = state.lock().unwrap().pipeline.description.clone();
+
+        // Create pipeline from string
+        let mut context = gstreamer::ParseContext::new();
+        pipeline = match gstreamer::parse_launch_full(
+            &pipeline_description,
+            Some(&mut context),
+            gstreamer::ParseFlags::empty(),
+        ) {
+            Ok(pipeline) => Some(pipeline),
+            Err(error) => {
+                let message = if let Some(gstreamer::ParseError::NoSuchElement) =
+                    error.kind::<gstreamer::ParseError>()
+                {
+                    format!(
+                        "GStreamer error: Missing element(s): {:?}",
+                        context.get_missing_elements()
+                    )
+                } else {
+                    format!("GStreamer error: Failed to parse pipeline: {}", error)
+                };
+                let _ = channel.send(message.clone());
+                pipeline_failed(&state, message, std::time::Duration::from_secs(0));
+                continue;
+            }
+        };
+
+        let bus = pipeline.as_ref().unwrap().get_bus().unwrap();
+
+        let queue_overruns = state.lock().unwrap().queue_overruns.clone();
+        watch_queue_overruns(pipeline.as_ref().unwrap(), &queue_overruns);
+
+        if let Err(error) = pipeline
+            .as_ref()
+            .unwrap()
+            .set_state(gstreamer::State::Playing)
+        {
+            let message = format!(
+                "GStreamer error: Unable to set the pipeline to the `Playing` state (check the bus for error messages): {}",
+                error
+            );
+            let _ = channel.send(message.clone());
+            if let Err(error) = pipeline.as_ref().unwrap().set_state(gstreamer::State::Null) {
+                warn!(
+                    "Unable to set the pipeline to the `Null` state: {:#?}",
+                    error
+                );
+            }
+            pipeline_failed(&state, message, std::time::Duration::from_secs(0));
+            continue;
+        }
+
+        state.lock().unwrap().gst_pipeline = pipeline.clone();
+        notify_state_changed(&state);
+
+        // Create dot file for the pipeline
+        gstreamer::debug_bin_to_dot_file(
+            pipeline
+                .as_ref()
+                .unwrap()
+                .downcast_ref::<gstreamer::Pipeline>()
+                .unwrap(),
+            gstreamer::DebugGraphDetails::all(),
+            "pipeline-started-video-stream-udp",
+        );
+
+        // Check if we need to break external loop.
+        // Some cameras have a duplicated timestamp when starting.
+        // to avoid restarting the camera once and once again,
+        // this checks for a maximum of 10 lost before restarting.
+        let mut previous_position: Option<gstreamer::ClockTime> = None;
+        let mut lost_timestamps: usize = 0;
+        let max_lost_timestamps: usize = 10;
+
+        // Warn when the queues keep getting full, the resolution or bitrate should be lowered
+        let mut previous_check = std::time::Instant::now();
+        let mut previous_overruns = queue_overruns.load(Ordering::Relaxed);
+
+        // Reason of the pipeline failure, None if it was stopped
+        let started = std::time::Instant::now();
+        let mut failure: Option<String> = None;
+
+        'innerLoop: loop {
+            let (kill, run, drain_on_stop) = {
+                let state = state.lock().unwrap();
+                (state.kill, state.run, state.drain_on_stop)
+            };
+            if kill || !run {
+                if drain_on_stop {
+                    finish_pipeline(pipeline.as_ref().unwrap(), &bus);
+                }
+                if kill {
+                    break 'externalLoop;
+                }
+                break 'innerLoop;
+            }
+
+            if previous_check.elapsed() >= OVERRUN_CHECK_INTERVAL {
+                let overruns = queue_overruns.load(Ordering::Relaxed);
+                if overruns - previous_overruns >= MAX_OVERRUNS_PER_INTERVAL {
+                    warn!(
+                        "Pipeline queues got full {} times in the last {} seconds, frames are being dropped: {}",
+                        overruns - previous_overruns,
+                        OVERRUN_CHECK_INTERVAL.as_secs(),
+                        pipeline_description
+                    );
+                }
+                previous_check = std::time::Instant::now();
+                previous_overruns = overruns;
+            }
+
+            // Restart pipeline if pipeline position do not change,
+            // occur if usb connection is lost and gstreamer do not detect it
+            match pipeline
+                .as_ref()
+                .unwrap()
+                .query_position::<gstreamer::ClockTime>()
+            {
+                Some(position) => {
+                    previous_position = match previous_position {
+                        Some(current_previous_position) => {
+                            if current_previous_position.nanoseconds() != Some(0)
+                                && current_previous_position.nanoseconds() == position.nanoseconds()
+                            {
+                                lost_timestamps += 1;
+                                let message =
+                                    format!("Position did not change {}", lost_timestamps);
+                                let _ = channel.send(message);
+                                let _ = channel
+                                    .send("Lost camera communication, restarting pipeline!".into());
+                            } else {
+                                // We are back in track, erase lost timestamps
+                                lost_timestamps = 0;
+                            }
+
+                            if lost_timestamps > max_lost_timestamps {
+                                failure = Some("Lost camera communication.".into());
+                                break 'innerLoop;
+                            }
+
+                            Some(position)
+                        }
+                        None => Some(position),
+                    }
+                }
+                None => {}
+            }
+
+            for msg in bus.timed_pop(gstreamer::ClockTime::from_mseconds(100)) {
+                use gstreamer::MessageView;
+
+                match msg.view() {
+                    MessageView::Eos(eos) => {
+                        let message = format!("GStreamer error: EOS received: {:#?}", eos);
+                        let _ = channel.send(message.clone());
+                        failure = Some(message);
+                        break 'innerLoop;
+                    }
+                    MessageView::StateChanged(state_changed) => {
+                        let pipeline = pipeline.as_ref().unwrap();
+                        let from_pipeline = state_changed.get_src().map_or(false, |source| {
+                            &source == pipeline.upcast_ref::<gstreamer::Object>()
+                        });
+                        if from_pipeline && state_changed.get_current() == gstreamer::State::Playing
+                        {
+                            let negotiated_caps = negotiated_caps(pipeline);
+                            match &negotiated_caps {
+                                Some(caps) => info!(
+                                    "Negotiated caps: format={}, width={}, height={}, framerate={}/{}",
+                                    caps.format,
+                                    caps.width,
+                                    caps.height,
+                                    caps.frame_interval.denominator,
+                                    caps.frame_interval.numerator
+                                ),
+                                None => warn!("Failed to get negotiated caps from pipeline."),
+                            }
+                            {
+                                let mut state = state.lock().unwrap();
+                                state.negotiated_caps = negotiated_caps;
+                                state.playing = true;
+                            }
+
+                            // The state is not locked by the callback
+                            let playing_callback = state.lock().unwrap().playing_callback.clone();
+                            if let Some(Callback(callback)) = playing_callback {
+                                callback();
+                            }
+                            notify_state_changed(&state);
+                        }
+                    }
+                    MessageView::Error(error) => {
+                        let message = format!(
+                            "GStreamer error: Error from {:?}: {} ({:?})",
+                            error.get_src().map(|s| s.get_path_string()),
+                            error.get_error(),
+                            error.get_debug()
+                        );
+                        let _ = channel.send(message.clone());
+                        failure = Some(message);
+                        break 'innerLoop;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        if let Err(error) = pipeline.as_ref().unwrap().set_state(gstreamer::State::Null) {
+            let _ = channel.send(format!(
+                "GStreamer error: Unable to set the pipeline to the `Null` state: {:#?}",
+                error
+            ));
+        }
+        {
+            let mut state = state.lock().unwrap();
+            state.gst_pipeline = None;
+            state.negotiated_caps = None;
+            state.playing = false;
+        }
+
+        // The loop will restart, wait longer the more the pipeline fails to avoid high cpu usage
+        match failure {
+            Some(error) => pipeline_failed(&state, error, started.elapsed()),
+            None => notify_state_changed(&state),
+        }
+    }
+
+    if pipeline.as_ref().is_some() {
+        if let Err(error) = pipeline.as_ref().unwrap().set_state(gstreamer::State::Null) {
+            let _ = channel.send(format!(
+                "GStreamer error: Unable to set the pipeline to the `Null` state: {:#?}",
+                error
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_delay() {
+        let max_delay = std::time::Duration::from_secs(10);
+        assert_eq!(restart_delay(1, max_delay), MIN_RESTART_DELAY);
+        assert_eq!(
+            restart_delay(2, max_delay),
+            std::time::Duration::from_millis(400)
+        );
+        assert_eq!(
+            restart_delay(3, max_delay),
+            std::time::Duration::from_millis(800)
+        );
+        assert_eq!(
+            restart_delay(6, max_delay),
+            std::time::Duration::from_millis(6400)
+        );
+        assert_eq!(restart_delay(7, max_delay), max_delay);
+        assert_eq!(restart_delay(u32::MAX, max_delay), max_delay);
+
+        // The maximum delay is configurable
+        let max_delay = std::time::Duration::from_secs(1);
+        assert_eq!(
+            restart_delay(3, max_delay),
+            std::time::Duration::from_millis(800)
+        );
+        assert_eq!(restart_delay(4, max_delay), max_delay);
+    }
+}
@@ -1,7 +1,10 @@
 use super::video_source::VideoSource;
 use super::video_source_gst::VideoSourceGst;
 use super::video_source_local::VideoSourceLocal;
+use log::*;
 use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::collections::BTreeMap;
 
 //TODO: Fix enum names to follow rust standards
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -10,7 +13,7 @@ pub enum VideoSourceType {
     Local(VideoSourceLocal),
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum VideoEncodeType {
     UNKNOWN(String),
     H265,
@@ -26,6 +29,69 @@ pub struct CaptureConfiguration {
     pub height: u32,
     pub width: u32,
     pub frame_interval: FrameInterval,
+    // Maximum number of frames between keyframes, uses the encoder default if not set
+    #[serde(default)]
+    pub keyframe_interval: Option<u32>,
+    // Number of threads used by software encoders, uses the command line default if not set
+    #[serde(default)]
+    pub encoder_threads: Option<u32>,
+    // Number of B-frames between reference frames, they add latency and some decoders
+    // only show the video after buffering them. Uses the encoder default (0 for x264) if not set
+    #[serde(default)]
+    pub b_frames: Option<u32>,
+    // Raw format delivered to the encoder when the video needs to be encoded
+    #[serde(default)]
+    pub pixel_format: PixelFormat,
+    // Use the closest resolution provided by the source if the requested one is not available
+    #[serde(default)]
+    pub allow_closest_resolution: bool,
+    // Buffer exchange method of v4l2 devices, GStreamer selects it if not set
+    #[serde(default)]
+    pub io_mode: Option<IoMode>,
+}
+
+// v4l2src io-mode values, dmabuf avoids copies when the encoder can import the buffers
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoMode {
+    Auto,
+    Rw,
+    Mmap,
+    Userptr,
+    Dmabuf,
+    DmabufImport,
+}
+
+impl std::fmt::Display for IoMode {
+    // Value name used by v4l2src
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            IoMode::Auto => "auto",
+            IoMode::Rw => "rw",
+            IoMode::Mmap => "mmap",
+            IoMode::Userptr => "userptr",
+            IoMode::Dmabuf => "dmabuf",
+            IoMode::DmabufImport => "dmabuf-import",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum PixelFormat {
+    #[default]
+    I420,
+    NV12,
+    YV12,
+    YUY2,
+    UYVY,
+}
+
+impl std::fmt::Display for PixelFormat {
+    // Format name used by GStreamer caps
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -47,12 +113,93 @@ pub struct FrameInterval {
     pub denominator: u32,
 }
 
+impl FrameInterval {
+    pub fn new(numerator: u32, denominator: u32) -> Result<Self, SimpleError> {
+        if numerator == 0 || denominator == 0 {
+            return Err(SimpleError::new(format!(
+                "Frame interval should not have zero values, used: {}/{}",
+                numerator, denominator
+            )));
+        }
+
+        return Ok(Self {
+            numerator,
+            denominator,
+        });
+    }
+}
+
+impl From<gstreamer::Fraction> for FrameInterval {
+    // The fraction is the framerate, the inverse of the frame interval
+    fn from(framerate: gstreamer::Fraction) -> Self {
+        let (numerator, denominator) = (*framerate.denom(), *framerate.numer());
+        if numerator <= 0 || denominator <= 0 {
+            warn!(
+                "Invalid framerate {}/{}, using 30/1 instead.",
+                denominator, numerator
+            );
+            return Self {
+                numerator: 1,
+                denominator: 30,
+            };
+        }
+
+        return Self {
+            numerator: numerator as u32,
+            denominator: denominator as u32,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoFeature {
+    Exposure,
+    WhiteBalance,
+    Focus,
+}
+
+// Control values of a video source by control name
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ControlValue {
+    pub control_id: u64,
+    pub value: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ControlsSnapshot {
+    pub controls: BTreeMap<String, i64>,
+    // Controls that failed to be read or applied, with the reason
+    #[serde(default)]
+    pub errors: BTreeMap<String, String>,
+}
+
+// Information reported by the v4l2 driver of a local device (VIDIOC_QUERYCAP)
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DeviceInformation {
+    pub driver: String,
+    pub driver_version: String,
+    pub card: String,
+    // Distinguishes identical cameras, e.g: usb-0000:08:00.3-1
+    pub bus_info: String,
+    pub capabilities: Vec<String>,
+}
+
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct Control {
     pub name: String,
     pub cpp_type: String,
     pub id: u64,
     pub configuration: ControlType,
+    pub state: ControlState,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ControlState {
+    pub is_disabled: bool,
+    pub is_inactive: bool,
+    // Read-only and volatile controls, the driver ignores or rejects their values
+    pub is_read_only: bool,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -77,6 +224,38 @@ pub struct ControlSlider {
     pub min: i32,
 }
 
+// Sliders can use a percentage of their range, so all controls can be shown in the same way
+impl ControlSlider {
+    // Value of the control for a percentage of its range, rounded to the nearest step
+    pub fn value_from_percentage(&self, percentage: i64) -> i64 {
+        let range = self.max as i64 - self.min as i64;
+        let step = (self.step as i64).max(1);
+        let steps = (range * percentage + 50 * step) / (100 * step);
+        return self.min as i64 + steps.min(range / step) * step;
+    }
+
+    // Percentage of the control range used by the value
+    pub fn percentage_from_value(&self, value: i64) -> i64 {
+        let range = self.max as i64 - self.min as i64;
+        if range <= 0 {
+            return 0;
+        }
+        let percentage = ((value - self.min as i64) * 100 + range / 2) / range;
+        return percentage.max(0).min(100);
+    }
+
+    // Same control with the values as percentages of the range
+    pub fn normalized(&self) -> ControlSlider {
+        return ControlSlider {
+            default: self.percentage_from_value(self.default as i64) as i32,
+            value: self.percentage_from_value(self.value),
+            step: 1,
+            max: 100,
+            min: 0,
+        };
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct ControlMenu {
     pub default: i32,
@@ -109,6 +288,41 @@ impl VideoEncodeType {
             _ => VideoEncodeType::UNKNOWN(fourcc.to_string()),
         };
     }
+
+    // Encodes that can be requested by name
+    pub fn known() -> Vec<VideoEncodeType> {
+        return vec![
+            VideoEncodeType::H264,
+            VideoEncodeType::H265,
+            VideoEncodeType::MJPG,
+            VideoEncodeType::YUYV,
+        ];
+    }
+}
+
+// Unrecognized encode names are deserialized as UNKNOWN, to be rejected with a helpful message
+impl<'de> Deserialize<'de> for VideoEncodeType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        enum Tagged {
+            UNKNOWN(String),
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Encode {
+            Name(String),
+            Tagged(Tagged),
+        }
+
+        return Ok(match Encode::deserialize(deserializer)? {
+            Encode::Name(name) => VideoEncodeType::known()
+                .into_iter()
+                .find(|encode| format!("{:?}", encode) == name)
+                .unwrap_or(VideoEncodeType::UNKNOWN(name)),
+            Encode::Tagged(Tagged::UNKNOWN(name)) => VideoEncodeType::UNKNOWN(name),
+        });
+    }
 }
 
 impl Default for ControlType {
@@ -119,3 +333,36 @@ impl Default for ControlType {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_interval() {
+        assert_eq!(
+            FrameInterval::new(1, 30).unwrap(),
+            FrameInterval {
+                numerator: 1,
+                denominator: 30
+            }
+        );
+        assert!(FrameInterval::new(0, 30).is_err());
+        assert!(FrameInterval::new(1, 0).is_err());
+
+        assert_eq!(
+            FrameInterval::from(gstreamer::Fraction::new(25, 1)),
+            FrameInterval {
+                numerator: 1,
+                denominator: 25
+            }
+        );
+        assert_eq!(
+            FrameInterval::from(gstreamer::Fraction::new(0, 1)),
+            FrameInterval {
+                numerator: 1,
+                denominator: 30
+            }
+        );
+    }
+}
@@ -3,6 +3,30 @@ use super::video_source_gst::VideoSourceGst;
 use super::video_source_local::VideoSourceLocal;
 use log::*;
 use simple_error::SimpleError;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Time that the enumerated sources are considered valid before being enumerated again
+const CACHE_TIME_TO_LIVE: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct VideoSourceCapabilities {
+    pub source: VideoSourceType,
+    pub formats: Vec<Format>,
+    pub controls: Vec<Control>,
+    pub device: Option<DeviceInformation>,
+}
+
+#[derive(Default)]
+struct CapabilitiesCache {
+    last_update: Option<Instant>,
+    sources: Vec<VideoSourceCapabilities>,
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<CapabilitiesCache> = Mutex::new(CapabilitiesCache::default());
+}
 
 pub trait VideoSource {
     fn name(&self) -> &String;
@@ -28,19 +52,71 @@ pub fn cameras_available() -> Vec<VideoSourceType> {
     .concat();
 }
 
+// Return the available sources with their formats and controls,
+// the result is cached to avoid enumerating the devices for each request
+pub fn cached_capabilities() -> Vec<VideoSourceCapabilities> {
+    let mut cache = CACHE.lock().unwrap();
+    let is_valid = cache.last_update.map_or(false, |last_update| {
+        last_update.elapsed() < CACHE_TIME_TO_LIVE
+    });
+
+    if !is_valid {
+        cache.sources = cameras_available()
+            .into_iter()
+            .map(|source| VideoSourceCapabilities {
+                formats: source.inner().formats(),
+                controls: source.inner().controls(),
+                device: device_information(&source),
+                source,
+            })
+            .collect();
+        cache.last_update = Some(Instant::now());
+    }
+
+    return cache.sources.clone();
+}
+
+// Driver information of local sources, other sources do not have it
+pub fn device_information(source: &VideoSourceType) -> Option<DeviceInformation> {
+    match source {
+        VideoSourceType::Local(local) => local.device_information(),
+        _ => None,
+    }
+}
+
+// Force the next cached_capabilities call to enumerate the sources again,
+// should be used when a device is added, removed or changed
+pub fn invalidate_cache() {
+    CACHE.lock().unwrap().last_update = None;
+}
+
 pub fn get_video_source(source_string: &str) -> Result<VideoSourceType, SimpleError> {
-    match cameras_available()
+    let cameras = cameras_available();
+    match cameras
         .iter()
         .find(|source| source.inner().source_string() == source_string)
     {
         Some(video_source) => Ok(video_source.clone()),
         None => Err(SimpleError::new(format!(
-            "Failed to find video source for: {}",
-            source_string
+            "The source string '{}' does not exist, the available options are: {:?}.",
+            source_string,
+            source_strings(&cameras)
         ))),
     }
 }
 
+// Source strings of the video sources that are available now
+pub fn sources_available() -> Vec<String> {
+    return source_strings(&cameras_available());
+}
+
+fn source_strings(cameras: &[VideoSourceType]) -> Vec<String> {
+    return cameras
+        .iter()
+        .map(|source| source.inner().source_string().to_string())
+        .collect();
+}
+
 pub fn set_control(source_string: &str, control_id: u64, value: i64) -> std::io::Result<()> {
     let cameras = cameras_available();
     let camera = cameras
@@ -48,31 +124,449 @@ pub fn set_control(source_string: &str, control_id: u64, value: i64) -> std::io:
         .find(|source| source.inner().source_string() == source_string);
 
     if let Some(camera) = camera {
+        check_writable(&camera.inner().controls(), control_id)?;
         debug!(
             "Set camera ({}) control ({}) value ({}).",
             source_string, control_id, value
         );
+        invalidate_cache();
         return camera.inner().set_control_by_id(control_id, value);
     }
 
-    let sources_available: Vec<String> = cameras
-        .iter()
-        .map(|source| source.inner().source_string().to_string())
-        .collect();
-
     return Err(std::io::Error::new(
         std::io::ErrorKind::NotFound,
         format!(
             "The source string '{}' does not exist, the available options are: {:?}.",
-            source_string, sources_available
+            source_string,
+            source_strings(&cameras)
         ),
     ));
 }
 
+// Value of a slider control for a percentage of its range
+pub fn normalized_control_value(
+    source_string: &str,
+    control_id: u64,
+    percentage: i64,
+) -> std::io::Result<i64> {
+    let camera = get_video_source(source_string)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::NotFound, error.to_string()))?;
+    return slider_value(&camera.inner().controls(), control_id, percentage);
+}
+
+fn slider_value(controls: &[Control], control_id: u64, percentage: i64) -> std::io::Result<i64> {
+    if !(0..=100).contains(&percentage) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Normalized values should be between 0 and 100, used: {}",
+                percentage
+            ),
+        ));
+    }
+
+    match controls.iter().find(|control| control.id == control_id) {
+        Some(Control {
+            configuration: ControlType::Slider(slider),
+            ..
+        }) => Ok(slider.value_from_percentage(percentage)),
+        Some(control) => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Control {} ({}) does not have a range, only sliders accept normalized values.",
+                control.name, control.id
+            ),
+        )),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Control {} does not exist.", control_id),
+        )),
+    }
+}
+
+// Writing to read-only controls is a silent no-op for most drivers, reject it instead
+fn check_writable(controls: &[Control], control_id: u64) -> std::io::Result<()> {
+    match controls.iter().find(|control| control.id == control_id) {
+        Some(control) if control.state.is_read_only => Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("Control {} ({}) is read-only.", control.name, control.id),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Standard v4l2 control ids for the automatic modes
+const V4L2_CID_EXPOSURE_AUTO: u64 = 0x009a0901;
+const V4L2_CID_AUTO_WHITE_BALANCE: u64 = 0x0098090c;
+const V4L2_CID_FOCUS_AUTO: u64 = 0x009a090c;
+
+// Find the control id and value to turn on or off an automatic mode
+fn auto_control(
+    controls: &[Control],
+    feature: AutoFeature,
+    auto: bool,
+) -> Result<(u64, i64), SimpleError> {
+    let control_id = match feature {
+        AutoFeature::Exposure => V4L2_CID_EXPOSURE_AUTO,
+        AutoFeature::WhiteBalance => V4L2_CID_AUTO_WHITE_BALANCE,
+        AutoFeature::Focus => V4L2_CID_FOCUS_AUTO,
+    };
+
+    let control = match controls.iter().find(|control| control.id == control_id) {
+        Some(control) => control,
+        None => {
+            return Err(SimpleError::new(format!(
+                "Camera does not expose {:?} control.",
+                feature
+            )))
+        }
+    };
+
+    match &control.configuration {
+        ControlType::Bool(_) | ControlType::Slider(_) => return Ok((control.id, auto as i64)),
+        ControlType::Menu(menu) => {
+            // Cameras usually support only some of the automatic modes
+            let option_names: &[&str] = if auto {
+                &[
+                    "auto mode",
+                    "aperture priority mode",
+                    "shutter priority mode",
+                ]
+            } else {
+                &["manual mode"]
+            };
+
+            for option_name in option_names {
+                if let Some(option) = menu
+                    .options
+                    .iter()
+                    .find(|option| option.name.to_lowercase() == *option_name)
+                {
+                    return Ok((control.id, option.value));
+                }
+            }
+
+            return Err(SimpleError::new(format!(
+                "Control {} does not have an option for auto {}: {:?}",
+                control.name,
+                auto,
+                menu.options
+                    .iter()
+                    .map(|option| &option.name)
+                    .collect::<Vec<_>>()
+            )));
+        }
+    }
+}
+
+pub fn set_auto(source_string: &str, feature: AutoFeature, auto: bool) -> Result<(), SimpleError> {
+    let video_source = get_video_source(source_string)?;
+    let (control_id, value) = auto_control(&video_source.inner().controls(), feature, auto)?;
+
+    debug!(
+        "Set camera ({}) {:?} auto ({}) with control ({}) value ({}).",
+        source_string, feature, auto, control_id, value
+    );
+    invalidate_cache();
+    if let Err(error) = video_source.inner().set_control_by_id(control_id, value) {
+        return Err(SimpleError::new(format!(
+            "Failed to set control {} on {}: {}",
+            control_id, source_string, error
+        )));
+    }
+    return Ok(());
+}
+
+// Read the current value of all controls, failures are reported by control instead of
+// failing the whole snapshot
+pub fn controls_snapshot(source_string: &str) -> Result<ControlsSnapshot, SimpleError> {
+    let video_source = get_video_source(source_string)?;
+    let mut snapshot = ControlsSnapshot::default();
+    for control in video_source.inner().controls() {
+        match video_source.inner().control_value_by_id(control.id) {
+            Ok(value) => {
+                snapshot.controls.insert(control.name, value);
+            }
+            Err(error) => {
+                snapshot.errors.insert(control.name, error.to_string());
+            }
+        }
+    }
+    return Ok(snapshot);
+}
+
+// Apply the control values of a snapshot, returning the controls that were changed and the ones that failed.
+// Automatic modes are applied first since they can make the manual controls inactive
+pub fn apply_controls_snapshot(
+    source_string: &str,
+    values: &BTreeMap<String, i64>,
+) -> Result<ControlsSnapshot, SimpleError> {
+    let video_source = get_video_source(source_string)?;
+    let controls = video_source.inner().controls();
+
+    let mut result = ControlsSnapshot::default();
+    for name in values.keys() {
+        if !controls.iter().any(|control| control.name == *name) {
+            result
+                .errors
+                .insert(name.clone(), "Control does not exist.".to_string());
+        }
+    }
+
+    let auto_controls = controls
+        .into_iter()
+        .filter(|control| is_auto_control(control.id))
+        .collect();
+    apply_snapshot_controls(&video_source, auto_controls, values, &mut result);
+    // The state of the manual controls depends on the automatic modes, it's read again
+    let manual_controls = video_source
+        .inner()
+        .controls()
+        .into_iter()
+        .filter(|control| !is_auto_control(control.id))
+        .collect();
+    apply_snapshot_controls(&video_source, manual_controls, values, &mut result);
+
+    invalidate_cache();
+    return Ok(result);
+}
+
+fn apply_snapshot_controls(
+    video_source: &VideoSourceType,
+    controls: Vec<Control>,
+    values: &BTreeMap<String, i64>,
+    result: &mut ControlsSnapshot,
+) {
+    for control in controls {
+        let value = match values.get(&control.name) {
+            Some(value) => *value,
+            None => continue,
+        };
+
+        let current_value = video_source.inner().control_value_by_id(control.id).ok();
+        if skip_snapshot_control(&control, value, current_value) {
+            continue;
+        }
+
+        match video_source.inner().set_control_by_id(control.id, value) {
+            Ok(_) => {
+                result.controls.insert(control.name, value);
+            }
+            Err(error) => {
+                result.errors.insert(control.name, error.to_string());
+            }
+        }
+    }
+}
+
+// Controls of a snapshot that are not restored: read-only controls can't be written,
+// disabled and inactive ones are ignored by the driver and the others may already have the value
+fn skip_snapshot_control(control: &Control, value: i64, current_value: Option<i64>) -> bool {
+    return control.state.is_read_only
+        || control.state.is_disabled
+        || control.state.is_inactive
+        || current_value == Some(value);
+}
+
+fn is_auto_control(control_id: u64) -> bool {
+    return [
+        V4L2_CID_EXPOSURE_AUTO,
+        V4L2_CID_AUTO_WHITE_BALANCE,
+        V4L2_CID_FOCUS_AUTO,
+    ]
+    .contains(&control_id);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn auto_controls() {
+        let controls = vec![
+            Control {
+                name: "Exposure, Auto".into(),
+                cpp_type: "int32".into(),
+                id: V4L2_CID_EXPOSURE_AUTO,
+                configuration: ControlType::Menu(ControlMenu {
+                    default: 3,
+                    value: 3,
+                    options: vec![
+                        ControlOption {
+                            name: "Manual Mode".into(),
+                            value: 1,
+                        },
+                        ControlOption {
+                            name: "Aperture Priority Mode".into(),
+                            value: 3,
+                        },
+                    ],
+                }),
+                state: ControlState::default(),
+            },
+            Control {
+                name: "White Balance Temperature, Auto".into(),
+                cpp_type: "bool".into(),
+                id: V4L2_CID_AUTO_WHITE_BALANCE,
+                configuration: ControlType::Bool(ControlBool {
+                    default: 1,
+                    value: 1,
+                }),
+                state: ControlState::default(),
+            },
+        ];
+
+        assert_eq!(
+            auto_control(&controls, AutoFeature::Exposure, true).unwrap(),
+            (V4L2_CID_EXPOSURE_AUTO, 3)
+        );
+        assert_eq!(
+            auto_control(&controls, AutoFeature::Exposure, false).unwrap(),
+            (V4L2_CID_EXPOSURE_AUTO, 1)
+        );
+        assert_eq!(
+            auto_control(&controls, AutoFeature::WhiteBalance, false).unwrap(),
+            (V4L2_CID_AUTO_WHITE_BALANCE, 0)
+        );
+        assert!(auto_control(&controls, AutoFeature::Focus, true).is_err());
+    }
+
+    #[test]
+    fn read_only_controls() {
+        let control = |id: u64, is_read_only: bool| Control {
+            name: "Gain".into(),
+            cpp_type: "int64".into(),
+            id,
+            configuration: ControlType::Slider(ControlSlider {
+                default: 0,
+                value: 42,
+                step: 1,
+                max: 255,
+                min: 0,
+            }),
+            state: ControlState {
+                is_read_only,
+                ..Default::default()
+            },
+        };
+        let controls = vec![control(1, false), control(2, true)];
+
+        assert!(check_writable(&controls, 1).is_ok());
+        assert!(check_writable(&controls, 2).is_err());
+        // Unknown controls are left for the driver to reject
+        assert!(check_writable(&controls, 3).is_ok());
+    }
+
+    #[test]
+    fn snapshot_controls() {
+        let control = |state: ControlState| Control {
+            name: "Exposure (Absolute)".into(),
+            cpp_type: "int32".into(),
+            id: 0x009a0902,
+            configuration: ControlType::Slider(ControlSlider {
+                default: 156,
+                value: 156,
+                step: 1,
+                max: 5000,
+                min: 1,
+            }),
+            state,
+        };
+
+        assert!(!skip_snapshot_control(
+            &control(ControlState::default()),
+            300,
+            Some(156)
+        ));
+        assert!(!skip_snapshot_control(
+            &control(ControlState::default()),
+            300,
+            None
+        ));
+        // Controls that already have the value are not changed
+        assert!(skip_snapshot_control(
+            &control(ControlState::default()),
+            156,
+            Some(156)
+        ));
+        for state in [
+            ControlState {
+                is_disabled: true,
+                ..Default::default()
+            },
+            ControlState {
+                is_inactive: true,
+                ..Default::default()
+            },
+            ControlState {
+                is_read_only: true,
+                ..Default::default()
+            },
+        ] {
+            assert!(skip_snapshot_control(&control(state), 300, Some(156)));
+        }
+    }
+
+    #[test]
+    fn normalized_controls() {
+        let slider = |min: i32, max: i32, step: i32| ControlSlider {
+            default: 0,
+            value: 0,
+            step,
+            max,
+            min,
+        };
+        let brightness = slider(-64, 64, 1);
+        assert_eq!(brightness.value_from_percentage(0), -64);
+        assert_eq!(brightness.value_from_percentage(50), 0);
+        assert_eq!(brightness.value_from_percentage(100), 64);
+        assert_eq!(brightness.percentage_from_value(0), 50);
+        assert_eq!(brightness.percentage_from_value(64), 100);
+
+        // Values are rounded to the nearest step inside the range
+        let gain = slider(0, 255, 10);
+        assert_eq!(gain.value_from_percentage(50), 130);
+        assert_eq!(gain.value_from_percentage(100), 250);
+        assert_eq!(gain.percentage_from_value(255), 100);
+        assert_eq!(slider(5, 5, 1).percentage_from_value(5), 0);
+
+        let normalized = ControlSlider {
+            default: -64,
+            value: 32,
+            ..brightness
+        }
+        .normalized();
+        assert_eq!((normalized.default, normalized.value), (0, 75));
+        assert_eq!(
+            (normalized.min, normalized.max, normalized.step),
+            (0, 100, 1)
+        );
+
+        let controls = vec![
+            Control {
+                name: "Brightness".into(),
+                cpp_type: "int32".into(),
+                id: 1,
+                configuration: ControlType::Slider(brightness),
+                state: ControlState::default(),
+            },
+            Control {
+                name: "Auto".into(),
+                cpp_type: "bool".into(),
+                id: 2,
+                configuration: ControlType::Bool(ControlBool {
+                    default: 1,
+                    value: 1,
+                }),
+                state: ControlState::default(),
+            },
+        ];
+        assert_eq!(slider_value(&controls, 1, 25).unwrap(), -32);
+        assert!(slider_value(&controls, 1, 101).is_err());
+        assert!(slider_value(&controls, 2, 50).is_err());
+        assert!(slider_value(&controls, 3, 50).is_err());
+    }
+
     #[test]
     fn simple_test() {
         println!("{:#?}", cameras_available());
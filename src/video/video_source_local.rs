@@ -130,6 +130,7 @@ impl VideoSourceLocal {
                 None => {
                     error!("Failed to find camera: {:#?}", self);
                     error!("Camera will be set as invalid.");
+                    video_source::invalidate_cache();
                     self.device_path = "".into();
                     return false;
                 }
@@ -142,6 +143,7 @@ impl VideoSourceLocal {
                         info!("Camera path changed.");
                         info!("Previous camera location: {:#?}", self);
                         info!("New camera location: {:#?}", camera);
+                        video_source::invalidate_cache();
                         *self = camera.clone();
                         return true;
                     }
@@ -151,6 +153,42 @@ impl VideoSourceLocal {
         }
         return true;
     }
+
+    // Query the driver information, None if the device does not answer
+    pub fn device_information(&self) -> Option<DeviceInformation> {
+        let caps = match Device::with_path(&self.device_path).and_then(|device| device.query_caps())
+        {
+            Ok(caps) => caps,
+            Err(error) => {
+                debug!(
+                    "Failed to query caps for device: {} {:#?}",
+                    self.device_path, error
+                );
+                return None;
+            }
+        };
+
+        let (major, minor, patch) = caps.version;
+        return Some(DeviceInformation {
+            driver: caps.driver,
+            driver_version: format!("{}.{}.{}", major, minor, patch),
+            card: caps.card,
+            bus_info: caps.bus,
+            capabilities: capability_names(caps.capabilities),
+        });
+    }
+}
+
+// Names of the capability flags in lowercase, e.g: video_capture, streaming
+fn capability_names(flags: v4l::capability::Flags) -> Vec<String> {
+    if flags.is_empty() {
+        return vec![];
+    }
+
+    return format!("{:?}", flags)
+        .split(" | ")
+        .map(|name| name.to_lowercase())
+        .collect();
 }
 
 fn convert_v4l_intervals(v4l_intervals: &[v4l::FrameInterval]) -> Vec<FrameInterval> {
@@ -186,7 +224,16 @@ impl VideoSource for VideoSourceLocal {
     }
 
     fn formats(&self) -> Vec<Format> {
-        let device = Device::with_path(&self.device_path).unwrap();
+        let device = match Device::with_path(&self.device_path) {
+            Ok(device) => device,
+            Err(error) => {
+                warn!(
+                    "Failed to open camera: {}, error: {:#?}",
+                    &self.device_path, error
+                );
+                return vec![];
+            }
+        };
         let v4l_formats = device.enum_formats().unwrap_or_default();
         let mut formats = vec![];
 
@@ -295,6 +342,14 @@ impl VideoSource for VideoSourceLocal {
             let mut control = Control {
                 name: v4l_control.name,
                 id: v4l_control.id as u64,
+                state: ControlState {
+                    is_disabled: v4l_control.flags.contains(v4l::control::Flags::DISABLED),
+                    is_inactive: v4l_control.flags.contains(v4l::control::Flags::INACTIVE),
+                    // Volatile controls are computed by the driver, e.g: gain in auto exposure mode
+                    is_read_only: v4l_control
+                        .flags
+                        .intersects(v4l::control::Flags::READ_ONLY | v4l::control::Flags::VOLATILE),
+                },
                 ..Default::default()
             };
             let value = self.control_value_by_id(v4l_control.id as u64);
@@ -453,4 +508,15 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn capability_flag_names() {
+        use v4l::capability::Flags;
+
+        assert!(capability_names(Flags::empty()).is_empty());
+        assert_eq!(
+            capability_names(Flags::VIDEO_CAPTURE | Flags::STREAMING | Flags::EXT_PIX_FORMAT),
+            vec!["video_capture", "ext_pix_format", "streaming"]
+        );
+    }
 }
@@ -1,5 +1,4 @@
 use super::types::*;
-use super::video_source::VideoSource;
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -112,13 +111,11 @@ impl Description {
     }
 }
 
-pub fn from_video_source(video_source: &dyn VideoSource) -> String {
-    let controls = video_source.controls();
-
+pub fn from_controls(name: &str, controls: &[Control]) -> String {
     let definition = Definition {
         version: 1,
         model: Model {
-            body: video_source.name().clone(),
+            body: name.to_string(),
         },
         vendor: Vendor {
             body: "Missing".into(),
@@ -187,7 +184,7 @@ mod tests {
         use crate::video::{video_source, video_source::VideoSource};
         for camera in video_source::cameras_available() {
             if let VideoSourceType::Local(camera) = camera {
-                let xml_string = from_video_source(&camera);
+                let xml_string = from_controls(camera.name(), &camera.controls());
                 println!("{}", xml_string);
             }
         }
@@ -1,5 +1,6 @@
+use crate::stream::stream_backend;
 use crate::stream::types::StreamInformation;
-use crate::video::types::VideoSourceType;
+use crate::video::types::{ControlValue, VideoSourceType};
 use crate::video::video_source::VideoSource;
 
 use serde::{Deserialize, Serialize};
@@ -10,10 +11,26 @@ use simple_error::SimpleError;
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct VideoAndStreamInformation {
     pub name: String,
+    // Disabled streams are kept in the settings but are not started
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Streams with lower priority are the first ones to be stopped when resources are scarce
+    #[serde(default)]
+    pub priority: u8,
+    // Human readable context of the stream, E.g: forward obstacle camera
+    #[serde(default)]
+    pub description: Option<String>,
+    // Controls applied to local sources before the stream starts
+    #[serde(default)]
+    pub controls: Vec<ControlValue>,
     pub stream_information: StreamInformation,
     pub video_source: VideoSourceType,
 }
 
+pub fn default_enabled() -> bool {
+    return true;
+}
+
 impl VideoAndStreamInformation {
     pub fn conflicts_with(&self, other: &VideoAndStreamInformation) -> Result<(), SimpleError> {
         if self.name == other.name {
@@ -30,9 +47,16 @@ impl VideoAndStreamInformation {
             )));
         }
 
+        // Endpoints are compared by their destination, the options don't matter
         use std::collections::HashSet;
-        let our_endpoints: HashSet<_> = self.stream_information.endpoints.iter().collect();
-        let other_endpoints: HashSet<_> = other.stream_information.endpoints.iter().collect();
+        let destinations = |endpoints: &[url::Url]| -> HashSet<String> {
+            endpoints
+                .iter()
+                .map(stream_backend::endpoint_destination)
+                .collect()
+        };
+        let our_endpoints = destinations(&self.stream_information.endpoints);
+        let other_endpoints = destinations(&other.stream_information.endpoints);
         let common_endpoints: HashSet<_> = our_endpoints.intersection(&other_endpoints).collect();
 
         if !common_endpoints.is_empty() {